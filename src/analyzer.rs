@@ -0,0 +1,207 @@
+use crate::control_flow::{self, Analysis, Flow, Instruction};
+use crate::disassembler;
+use crate::platform::Platform;
+use crate::quirks::{self, Quirks};
+
+// Something found at an address which supports a recommendation
+pub struct Hint {
+    pub address: u16,
+    pub text: String,
+}
+
+pub struct Report {
+    pub rom_size: usize,
+    pub reachable_instructions: usize,
+    pub platform: Platform,
+    pub platform_evidence: Vec<Hint>,
+    pub quirk_hints: Vec<Hint>,
+    pub quirks: Quirks,
+    pub cpu_hz: u64,
+}
+
+// Scan the reachable code of a rom for instructions which only exist on later
+// platforms, and for patterns which only work with particular quirks
+pub fn analyze(rom: &[u8]) -> Report {
+    // Decode as widely as possible, so that extended instructions are found
+    let analysis = control_flow::analyze(rom, Platform::XoChip);
+
+    let mut platform = Platform::Chip8;
+    let mut platform_evidence = Vec::new();
+    for instruction in analysis.instructions.values() {
+        let required = required_platform(instruction);
+        if required > Platform::Chip8 {
+            platform = platform.max(required);
+            platform_evidence.push(Hint {
+                address: instruction.address,
+                text: format!("{} needs {}", describe(instruction), required.name()),
+            });
+        }
+    }
+
+    let shift_hints = shift_hints(&analysis);
+    let load_store_hints = load_store_hints(&analysis);
+    let jump_hints: Vec<Hint> = analysis.indirect_jumps.iter()
+        .map(|address| Hint {
+            address: *address,
+            text: format!("{} jumps to NNN + V0, or XNN + VX on SCHIP", describe(&analysis.instructions[address])),
+        })
+        .collect();
+
+    let quirks = match platform {
+        Platform::Chip8 if !shift_hints.is_empty() || !load_store_hints.is_empty() => Quirks::cosmac(),
+        _ => Quirks::for_platform(platform),
+    };
+
+    let mut quirk_hints = Vec::new();
+    quirk_hints.extend(shift_hints);
+    quirk_hints.extend(load_store_hints);
+    quirk_hints.extend(jump_hints);
+    quirk_hints.sort_by_key(|hint| hint.address);
+
+    Report {
+        rom_size: rom.len(),
+        reachable_instructions: analysis.instructions.len(),
+        platform,
+        platform_evidence,
+        quirk_hints,
+        quirks,
+        cpu_hz: quirks::default_cpu_hz(platform),
+    }
+}
+
+fn describe(instruction: &Instruction) -> String {
+    let raw: String = instruction.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    let mnemonic = disassembler::decode(&instruction.bytes, Platform::XoChip).mnemonic.unwrap_or_default();
+    format!("{} ({})", raw, mnemonic)
+}
+
+fn required_platform(instruction: &Instruction) -> Platform {
+    if instruction.bytes.len() == 4 {
+        return Platform::XoChip;
+    }
+
+    let op_code = instruction.op_code();
+    match (op_code.op, op_code.x, op_code.y, op_code.n) {
+        (0x0, 0x0, 0xD, _) | (0x5, _, _, 0x2) | (0x5, _, _, 0x3) | (0xF, _, 0x0, 0x1) | (0xF, 0x0, 0x0, 0x2) | (0xF, _, 0x3, 0xA) => {
+            Platform::XoChip
+        },
+        (0x0, 0x0, 0xC, _) | (0x0, 0x0, 0xF, 0xB..=0xF) | (0xF, _, 0x3, 0x0) | (0xF, _, 0x7, 0x5) | (0xF, _, 0x8, 0x5) => {
+            Platform::Schip
+        },
+        _ => Platform::Chip8,
+    }
+}
+
+fn changes_i(instruction: &Instruction) -> bool {
+    let op_code = instruction.op_code();
+    instruction.bytes.len() == 4
+        || op_code.op == 0xA
+        || matches!(op_code.pattern(), "FX1E" | "FX29")
+        || (op_code.op == 0xF && op_code.nn == 0x30)
+}
+
+fn is_load_store(instruction: &Instruction) -> Option<&'static str> {
+    match instruction.op_code().pattern() {
+        pattern @ "FX55" | pattern @ "FX65" => Some(pattern),
+        _ => None,
+    }
+}
+
+// Modern interpreters shift VX in place and ignore Y, so a different Y
+// suggests the rom was written for the original, which shifts VY into VX.
+// Y = 0 is how "don't care" is usually written, so proves nothing.
+fn shift_hints(analysis: &Analysis) -> Vec<Hint> {
+    analysis.instructions.values()
+        .filter(|instruction| {
+            let op_code = instruction.op_code();
+            matches!(op_code.pattern(), "8XY6" | "8XYE") && op_code.x != op_code.y && op_code.y != 0
+        })
+        .map(|instruction| Hint {
+            address: instruction.address,
+            text: format!("{} shifts with X != Y, as if VY is shifted into VX", describe(instruction)),
+        })
+        .collect()
+}
+
+// Saving or loading twice in a row without moving I, or repeatedly in a
+// loop, only makes sense if I is incremented by each save or load
+fn load_store_hints(analysis: &Analysis) -> Vec<Hint> {
+    let mut hints = Vec::new();
+
+    let mut previous: Option<&'static str> = None;
+    for instruction in analysis.instructions.values() {
+        let address = instruction.address;
+        let is_block_start = analysis.jump_targets.contains(&address) || analysis.call_targets.contains(&address);
+        if is_block_start || changes_i(instruction) {
+            previous = None;
+        }
+
+        if let Some(pattern) = is_load_store(instruction) {
+            if previous == Some(pattern) {
+                hints.push(Hint {
+                    address,
+                    text: format!("{} follows another {} without moving I, as if I is incremented", describe(instruction), pattern),
+                });
+            }
+            previous = Some(pattern);
+        }
+
+        match instruction.flow {
+            Flow::Next | Flow::Skip => {},
+            _ => previous = None,
+        }
+
+        // A loop back to an earlier address
+        if let Flow::Jump(target) = instruction.flow {
+            if target <= address {
+                let body: Vec<&Instruction> = analysis.instructions.range(target..=address)
+                    .map(|(_, instruction)| instruction)
+                    .collect();
+                let load_store = body.iter().find(|instruction| is_load_store(instruction).is_some());
+                if let Some(load_store) = load_store {
+                    if !body.iter().any(|instruction| changes_i(instruction)) {
+                        hints.push(Hint {
+                            address: load_store.address,
+                            text: format!("{} repeats in a loop without moving I, as if I is incremented", describe(load_store)),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    hints
+}
+
+impl Report {
+    pub fn describe(&self) -> String {
+        let mut text = String::new();
+
+        text.push_str(&format!("Size: {} bytes, {} reachable instructions\n", self.rom_size, self.reachable_instructions));
+
+        text.push_str(&format!("\nPlatform: {}\n", self.platform.name()));
+        if self.platform_evidence.is_empty() {
+            text.push_str("  No SCHIP or XO-CHIP instructions found\n");
+        }
+        for hint in &self.platform_evidence {
+            text.push_str(&format!("  {:#05X}  {}\n", hint.address, hint.text));
+        }
+
+        text.push_str("\nQuirk hints:\n");
+        if self.quirk_hints.is_empty() {
+            text.push_str("  None found\n");
+        }
+        for hint in &self.quirk_hints {
+            text.push_str(&format!("  {:#05X}  {}\n", hint.address, hint.text));
+        }
+
+        text.push_str(&format!(
+            "\nRecommended: --platform {} --quirks {} --cpu-hz {}\n",
+            self.platform.name(),
+            self.quirks.name().unwrap_or("modern"),
+            self.cpu_hz,
+        ));
+
+        text
+    }
+}
@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::fmt;
+
+const ROM_START: u32 = 0x200;
+
+// Constants can refer to each other, but not forever
+const MAX_EXPRESSION_DEPTH: usize = 32;
+
+#[derive(Debug)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    V(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+    Long(String),
+    Value(String),
+    Text(Vec<u8>),
+}
+
+struct Statement {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+// Assemble Cowgod style source, such as "LD V1, 0x20" or "DRW V0, V1, 5",
+// into a rom which is loaded at 0x200. Supports labels ("loop:"), constants
+// ("SPEED EQU 4" or "SPEED = 4"), data ("db 0xF0, 0x90" and "dw 0x1234"),
+// "org" to move forward to an address, and ; comments.
+pub fn assemble(source: &str) -> Result<Vec<u8>, Vec<AssemblyError>> {
+    let mut errors = Vec::new();
+    let mut symbols: HashMap<String, String> = HashMap::new();
+    let mut statements = Vec::new();
+
+    // First pass: find where everything goes, and what every name means
+    let mut address = ROM_START;
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut error = |message: String| errors.push(AssemblyError { line: line_number, message });
+
+        let mut text = strip_comment(line).trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        // Constants
+        let tokens: Vec<&str> = text.splitn(3, char::is_whitespace).filter(|token| !token.is_empty()).collect();
+        let constant = match tokens.as_slice() {
+            [name, equ, value] if equ.eq_ignore_ascii_case("equ") || *equ == "=" => Some((*name, *value)),
+            _ => text.find('=')
+                .map(|index| (text[..index].trim(), text[index + 1..].trim()))
+                .filter(|(name, _)| is_identifier(name)),
+        };
+        if let Some((name, value)) = constant {
+            if !is_identifier(name) {
+                error(format!("Invalid constant name {}", name));
+            } else if symbols.insert(name.to_lowercase(), value.trim().to_string()).is_some() {
+                error(format!("{} is already defined", name));
+            }
+            continue;
+        }
+
+        // Labels, optionally followed by a statement
+        if let Some(index) = text.find(':') {
+            let name = text[..index].trim();
+            if is_identifier(name) {
+                if symbols.insert(name.to_lowercase(), address.to_string()).is_some() {
+                    error(format!("{} is already defined", name));
+                }
+                text = text[index + 1..].trim();
+                if text.is_empty() {
+                    continue;
+                }
+            }
+        }
+
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(index) => (&text[..index], text[index..].trim()),
+            None => (text, ""),
+        };
+        let mnemonic = mnemonic.to_uppercase();
+
+        let operands = match parse_operands(rest) {
+            Ok(operands) => operands,
+            Err(message) => {
+                error(message);
+                continue;
+            }
+        };
+
+        if mnemonic == "ORG" {
+            match operands.as_slice() {
+                [Operand::Value(value)] => match evaluate(value, &symbols, 0) {
+                    Ok(target) if target >= address => {
+                        statements.push(Statement {
+                            line: line_number,
+                            mnemonic: "DB".to_string(),
+                            operands: vec![Operand::Text(vec![0; (target - address) as usize])],
+                        });
+                        address = target;
+                    },
+                    Ok(target) => error(format!("Can't org backwards to {:#05X}", target)),
+                    Err(message) => error(message),
+                },
+                _ => error("Expected an address".to_string()),
+            }
+            continue;
+        }
+
+        let size = size_of(&mnemonic, &operands);
+        statements.push(Statement {
+            line: line_number,
+            mnemonic,
+            operands,
+        });
+        address += size;
+    }
+
+    // Second pass: encode everything, now that all of the labels are known
+    let mut rom = Vec::new();
+    for statement in &statements {
+        match encode(statement, &symbols) {
+            Ok(bytes) => rom.extend(bytes),
+            Err(message) => errors.push(AssemblyError { line: statement.line, message }),
+        }
+    }
+
+    if address > 0x1000 {
+        errors.push(AssemblyError {
+            line: source.lines().count(),
+            message: format!("Program is {} bytes, which doesn't fit in memory", address - ROM_START),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(rom)
+    } else {
+        errors.sort_by_key(|error| error.line);
+        Err(errors)
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..index],
+            _ => {},
+        }
+    }
+    line
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {},
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let mut in_string = false;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                operands.push(text[start..index].trim());
+                start = index + 1;
+            },
+            _ => {},
+        }
+    }
+    operands.push(text[start..].trim());
+    operands
+}
+
+fn parse_operands(text: &str) -> Result<Vec<Operand>, String> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    split_operands(text).into_iter()
+        .map(|operand| {
+            if operand.is_empty() {
+                return Err("Missing operand".to_string());
+            }
+
+            let upper = operand.to_uppercase();
+            let parsed = match upper.as_str() {
+                "I" => Operand::I,
+                "[I]" => Operand::IndirectI,
+                "DT" => Operand::DelayTimer,
+                "ST" => Operand::SoundTimer,
+                "K" => Operand::Key,
+                "F" => Operand::Font,
+                "HF" => Operand::BigFont,
+                "B" => Operand::Bcd,
+                "R" => Operand::Flags,
+                _ if upper.len() == 2 && upper.starts_with('V') && upper.as_bytes()[1].is_ascii_hexdigit() => {
+                    Operand::V(u8::from_str_radix(&upper[1..], 16).unwrap())
+                },
+                _ if upper.starts_with("LONG ") => Operand::Long(operand[5..].trim().to_string()),
+                _ if operand.starts_with('"') => {
+                    if operand.len() < 2 || !operand.ends_with('"') {
+                        return Err(format!("Unterminated string {}", operand));
+                    }
+                    Operand::Text(operand.as_bytes()[1..operand.len() - 1].to_vec())
+                },
+                _ => Operand::Value(operand.to_string()),
+            };
+            Ok(parsed)
+        })
+        .collect()
+}
+
+fn size_of(mnemonic: &str, operands: &[Operand]) -> u32 {
+    match (mnemonic, operands) {
+        ("DB", _) => operands.iter()
+            .map(|operand| match operand {
+                Operand::Text(text) => text.len() as u32,
+                _ => 1,
+            })
+            .sum(),
+        ("DW", _) => 2 * operands.len() as u32,
+        ("LD", [Operand::I, Operand::Long(_)]) => 4,
+        _ => 2,
+    }
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('$')).or_else(|| lower.strip_prefix('#')) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b").or_else(|| lower.strip_prefix('%')) {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+// Evaluate sums and differences of numbers, labels and constants
+fn evaluate(expression: &str, symbols: &HashMap<String, String>, depth: usize) -> Result<u32, String> {
+    if depth > MAX_EXPRESSION_DEPTH {
+        return Err(format!("{} refers to itself", expression));
+    }
+
+    let mut total: i64 = 0;
+    let mut sign = 1;
+    let mut term = String::new();
+    let mut terms = Vec::new();
+    for c in expression.chars().chain(std::iter::once('+')) {
+        match c {
+            '+' | '-' if !term.trim().is_empty() => {
+                terms.push((sign, term.trim().to_string()));
+                term.clear();
+                sign = if c == '-' { -1 } else { 1 };
+            },
+            '-' => sign = -sign,
+            '+' => {},
+            _ => term.push(c),
+        }
+    }
+
+    if terms.is_empty() {
+        return Err("Missing value".to_string());
+    }
+
+    for (sign, term) in terms {
+        let value = match parse_number(&term) {
+            Some(value) => value,
+            None => match symbols.get(&term.to_lowercase()) {
+                Some(definition) => i64::from(evaluate(definition, symbols, depth + 1)?),
+                None if is_identifier(&term) => return Err(format!("Undefined name {}", term)),
+                None => return Err(format!("Invalid value {}", term)),
+            },
+        };
+        total += sign * value;
+    }
+
+    // Negative numbers are allowed, as two's complement
+    if !(-0x8000..=0xFFFF).contains(&total) {
+        return Err(format!("Value {} is out of range", expression));
+    }
+    Ok((total & 0xFFFF) as u32)
+}
+
+fn sized(value: u32, bits: u32, expression: &str) -> Result<u16, String> {
+    // Small negative numbers have been wrapped to 16 bits, so unwrap them
+    let value = if value >= 0x8000 && bits < 16 && value >= 0x10000 - (1 << (bits - 1)) {
+        value & ((1 << bits) - 1)
+    } else {
+        value
+    };
+
+    if value >= (1 << bits) {
+        return Err(format!("{} ({:#X}) doesn't fit in {} bits", expression, value, bits));
+    }
+    Ok(value as u16)
+}
+
+fn encode(statement: &Statement, symbols: &HashMap<String, String>) -> Result<Vec<u8>, String> {
+    let value = |operand: &Operand, bits: u32| -> Result<u16, String> {
+        match operand {
+            Operand::Value(expression) => sized(evaluate(expression, symbols, 0)?, bits, expression),
+            _ => Err(format!("Expected a value, not {:?}", operand)),
+        }
+    };
+    let word = |word: u16| Ok(vec![(word >> 8) as u8, word as u8]);
+    let xy = |op: u16, x: u8, y: u8, n: u16| word((op << 12) | (u16::from(x) << 8) | (u16::from(y) << 4) | n);
+    let xnn = |op: u16, x: u8, nn: u16| word((op << 12) | (u16::from(x) << 8) | nn);
+
+    use Operand::*;
+    match (statement.mnemonic.as_str(), statement.operands.as_slice()) {
+        ("DB", operands) => {
+            let mut bytes = Vec::new();
+            for operand in operands {
+                match operand {
+                    Text(text) => bytes.extend(text),
+                    _ => bytes.push(value(operand, 8)? as u8),
+                }
+            }
+            Ok(bytes)
+        },
+        ("DW", operands) => {
+            let mut bytes = Vec::new();
+            for operand in operands {
+                let word = value(operand, 16)?;
+                bytes.push((word >> 8) as u8);
+                bytes.push(word as u8);
+            }
+            Ok(bytes)
+        },
+
+        ("CLS", []) => word(0x00E0),
+        ("RET", []) => word(0x00EE),
+        ("SYS", [address]) => word(value(address, 12)?),
+        ("JP", [V(0), address]) => word(0xB000 | value(address, 12)?),
+        ("JP", [address]) => word(0x1000 | value(address, 12)?),
+        ("CALL", [address]) => word(0x2000 | value(address, 12)?),
+        ("SE", [V(x), V(y)]) => xy(0x5, *x, *y, 0x0),
+        ("SE", [V(x), byte]) => xnn(0x3, *x, value(byte, 8)?),
+        ("SNE", [V(x), V(y)]) => xy(0x9, *x, *y, 0x0),
+        ("SNE", [V(x), byte]) => xnn(0x4, *x, value(byte, 8)?),
+        ("LD", [V(x), V(y)]) => xy(0x8, *x, *y, 0x0),
+        ("LD", [V(x), DelayTimer]) => xnn(0xF, *x, 0x07),
+        ("LD", [V(x), Key]) => xnn(0xF, *x, 0x0A),
+        ("LD", [V(x), IndirectI]) => xnn(0xF, *x, 0x65),
+        ("LD", [V(x), Flags]) => xnn(0xF, *x, 0x85),
+        ("LD", [V(x), byte]) => xnn(0x6, *x, value(byte, 8)?),
+        ("LD", [I, Long(expression)]) => {
+            let address = sized(evaluate(expression, symbols, 0)?, 16, expression)?;
+            Ok(vec![0xF0, 0x00, (address >> 8) as u8, address as u8])
+        },
+        ("LD", [I, address]) => word(0xA000 | value(address, 12)?),
+        ("LD", [DelayTimer, V(x)]) => xnn(0xF, *x, 0x15),
+        ("LD", [SoundTimer, V(x)]) => xnn(0xF, *x, 0x18),
+        ("LD", [Font, V(x)]) => xnn(0xF, *x, 0x29),
+        ("LD", [BigFont, V(x)]) => xnn(0xF, *x, 0x30),
+        ("LD", [Bcd, V(x)]) => xnn(0xF, *x, 0x33),
+        ("LD", [IndirectI, V(x)]) => xnn(0xF, *x, 0x55),
+        ("LD", [Flags, V(x)]) => xnn(0xF, *x, 0x75),
+        ("ADD", [I, V(x)]) => xnn(0xF, *x, 0x1E),
+        ("ADD", [V(x), V(y)]) => xy(0x8, *x, *y, 0x4),
+        ("ADD", [V(x), byte]) => xnn(0x7, *x, value(byte, 8)?),
+        ("OR", [V(x), V(y)]) => xy(0x8, *x, *y, 0x1),
+        ("AND", [V(x), V(y)]) => xy(0x8, *x, *y, 0x2),
+        ("XOR", [V(x), V(y)]) => xy(0x8, *x, *y, 0x3),
+        ("SUB", [V(x), V(y)]) => xy(0x8, *x, *y, 0x5),
+        ("SHR", [V(x)]) => xy(0x8, *x, *x, 0x6),
+        ("SHR", [V(x), V(y)]) => xy(0x8, *x, *y, 0x6),
+        ("SUBN", [V(x), V(y)]) => xy(0x8, *x, *y, 0x7),
+        ("SHL", [V(x)]) => xy(0x8, *x, *x, 0xE),
+        ("SHL", [V(x), V(y)]) => xy(0x8, *x, *y, 0xE),
+        ("RND", [V(x), byte]) => xnn(0xC, *x, value(byte, 8)?),
+        ("DRW", [V(x), V(y), n]) => xy(0xD, *x, *y, value(n, 4)?),
+        ("SKP", [V(x)]) => xnn(0xE, *x, 0x9E),
+        ("SKNP", [V(x)]) => xnn(0xE, *x, 0xA1),
+
+        // SCHIP
+        ("SCD", [n]) => word(0x00C0 | value(n, 4)?),
+        ("SCR", []) => word(0x00FB),
+        ("SCL", []) => word(0x00FC),
+        ("EXIT", []) => word(0x00FD),
+        ("LOW", []) => word(0x00FE),
+        ("HIGH", []) => word(0x00FF),
+
+        // XO-CHIP
+        ("SCU", [n]) => word(0x00D0 | value(n, 4)?),
+        ("SAVE", [V(x), V(y)]) => xy(0x5, *x, *y, 0x2),
+        ("LOAD", [V(x), V(y)]) => xy(0x5, *x, *y, 0x3),
+        ("PLANE", [n]) => word(0xF001 | (value(n, 4)? << 8)),
+        ("AUDIO", []) => word(0xF002),
+        ("PITCH", [V(x)]) => xnn(0xF, *x, 0x3A),
+
+        (mnemonic, operands) if is_known(mnemonic) => {
+            Err(format!("Invalid operands for {}: {}", mnemonic, describe(operands)))
+        },
+        (mnemonic, _) => Err(format!("Unknown instruction {}", mnemonic)),
+    }
+}
+
+fn is_known(mnemonic: &str) -> bool {
+    [
+        "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR",
+        "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SCU",
+        "SAVE", "LOAD", "PLANE", "AUDIO", "PITCH",
+    ].contains(&mnemonic)
+}
+
+fn describe(operands: &[Operand]) -> String {
+    let operands: Vec<String> = operands.iter()
+        .map(|operand| match operand {
+            Operand::V(x) => format!("V{:X}", x),
+            Operand::I => "I".to_string(),
+            Operand::IndirectI => "[I]".to_string(),
+            Operand::DelayTimer => "DT".to_string(),
+            Operand::SoundTimer => "ST".to_string(),
+            Operand::Key => "K".to_string(),
+            Operand::Font => "F".to_string(),
+            Operand::BigFont => "HF".to_string(),
+            Operand::Bcd => "B".to_string(),
+            Operand::Flags => "R".to_string(),
+            Operand::Long(expression) => format!("LONG {}", expression),
+            Operand::Value(expression) => expression.clone(),
+            Operand::Text(_) => "a string".to_string(),
+        })
+        .collect();
+    operands.join(", ")
+}
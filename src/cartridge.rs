@@ -0,0 +1,95 @@
+use crate::gif;
+use crate::json::Json;
+use crate::platform::Platform;
+use crate::quirks::{self, Quirks};
+
+// Octo's instructions per frame, when a cartridge doesn't say
+const DEFAULT_TICKRATE: f64 = 20.0;
+
+// An Octo cartridge: a GIF whose pixels carry the program's source and
+// Octo's options for running it
+pub struct Cartridge {
+    pub source: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub cpu_hz: u64,
+    // Background, foreground, then the other XO-CHIP plane colours
+    pub colors: Vec<(u8, u8, u8)>,
+}
+
+pub fn is_cartridge(data: &[u8]) -> bool {
+    data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")
+}
+
+pub fn decode(data: &[u8]) -> Result<Cartridge, String> {
+    let frames = gif::decode_frames(data)?;
+
+    // Each byte is spread across four pixels, two bits in the bottom of each
+    // palette index, most significant first
+    let pixels: Vec<u8> = frames.concat();
+    let bytes: Vec<u8> = pixels.chunks_exact(4)
+        .map(|pixels| pixels.iter().fold(0, |byte, pixel| (byte << 2) | (pixel & 3)))
+        .collect();
+
+    // A 32 bit length, then that much JSON
+    let length = bytes.get(..4)
+        .map(|length| u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize)
+        .ok_or("Not an Octo cartridge: the image is empty")?;
+    let payload = bytes.get(4..4 + length)
+        .ok_or("Not an Octo cartridge: the image is too small for its program")?;
+    let payload = Json::parse(&String::from_utf8_lossy(payload))
+        .map_err(|e| format!("Not an Octo cartridge: {}", e))?;
+
+    let source = payload.get("program")
+        .and_then(Json::as_str)
+        .ok_or("Octo cartridge has no program")?
+        .to_string();
+
+    let options = payload.get("options");
+    let option = |name: &str| options.and_then(|options| options.get(name));
+    let flag = |name: &str| option(name).and_then(Json::as_bool).unwrap_or(false);
+
+    // Octo's memory size is the only hint of the platform: 3216 bytes for the
+    // VIP, 3583 for SCHIP, 3584 for Octo's own CHIP-8 and 65024 for XO-CHIP
+    let platform = match option("maxSize").and_then(Json::as_f64) {
+        Some(size) if size > 3584.0 => Platform::XoChip,
+        Some(3583.0) => Platform::Schip,
+        _ => Platform::Chip8,
+    };
+
+    let quirks = Quirks {
+        shift_uses_vy: !flag("shiftQuirks"),
+        load_store_increments_i: !flag("loadStoreQuirks"),
+        jump_uses_vx: flag("jumpQuirks"),
+        vf_reset: flag("logicQuirks"),
+    };
+
+    let tickrate = option("tickrate").and_then(Json::as_f64).unwrap_or(DEFAULT_TICKRATE);
+    let cpu_hz = (tickrate * 60.0) as u64;
+
+    let colors = ["backgroundColor", "fillColor", "fillColor2", "blendColor"].iter()
+        .map_while(|name| option(name).and_then(Json::as_str).and_then(parse_color))
+        .collect();
+
+    Ok(Cartridge {
+        source,
+        platform,
+        quirks,
+        cpu_hz: if cpu_hz > 0 { cpu_hz } else { quirks::default_cpu_hz(platform) },
+        colors,
+    })
+}
+
+fn parse_color(text: &str) -> Option<(u8, u8, u8)> {
+    let hex = text.strip_prefix('#')?;
+    let channel = |index: usize| hex.get(index..index + 2).and_then(|channel| u8::from_str_radix(channel, 16).ok());
+    match hex.len() {
+        6 => Some((channel(0)?, channel(2)?, channel(4)?)),
+        // Octo's short form, such as #F00
+        3 => {
+            let digit = |index: usize| hex.get(index..index + 1).and_then(|digit| u8::from_str_radix(digit, 16).ok()).map(|digit| digit * 17);
+            Some((digit(0)?, digit(1)?, digit(2)?))
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,268 @@
+use std::fs;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::loader;
+use crate::symbols::parse_address;
+
+// How many candidates to list before giving up
+const MAX_LISTED: usize = 32;
+
+pub const HELP: &str = "\
+Cheat commands:
+    new                   Start a search over all of RAM
+    eq | ne               Keep addresses whose value is equal to, or changed from, the last search
+    gt | lt               Keep addresses whose value increased, or decreased, since the last search
+    <value>               Keep addresses whose value is now <value>
+    list                  Show the remaining candidates
+    freeze <addr> <value> [description]
+    unfreeze <addr>
+    cheats                Show the frozen addresses
+    save                  Write the frozen addresses to the cheat file";
+
+// Addresses held at a fixed value, as in a cheat file of lines such as
+// "0x2F0 0x09 infinite lives", reapplied every frame
+pub struct Cheats {
+    // Sorted by address
+    freezes: Vec<Freeze>,
+}
+
+pub struct Freeze {
+    pub address: u16,
+    pub value: u8,
+    pub description: String,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Self {
+            freezes: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read cheat file {}: {}", path, e))?;
+        Cheats::parse(&text)
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // The cheat file that sits alongside a rom, e.g. pong.cht for pong.ch8
+    pub fn default_path(rom_file: &str) -> String {
+        loader::companion_path(rom_file, "cht").to_string_lossy().into_owned()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut cheats = Cheats::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let freeze = parse_freeze(line)
+                .map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+            cheats.freeze(freeze);
+        }
+        Ok(cheats)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut text = String::new();
+        for freeze in &self.freezes {
+            let line = format!("{:#05X} {:#04X} {}", freeze.address, freeze.value, freeze.description);
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        fs::write(path, text)
+            .map_err(|e| format!("Failed to write cheat file {}: {}", path, e))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.freezes.is_empty()
+    }
+
+    pub fn freezes(&self) -> &[Freeze] {
+        &self.freezes
+    }
+
+    pub fn freeze(&mut self, freeze: Freeze) {
+        self.unfreeze(freeze.address);
+        let index = self.freezes.partition_point(|other| other.address < freeze.address);
+        self.freezes.insert(index, freeze);
+    }
+
+    pub fn unfreeze(&mut self, address: u16) -> bool {
+        let count = self.freezes.len();
+        self.freezes.retain(|freeze| freeze.address != address);
+        self.freezes.len() != count
+    }
+
+    // Write every frozen value back into RAM
+    pub fn apply(&self, ram: &mut [u8; 4096]) {
+        for freeze in &self.freezes {
+            ram[usize::from(freeze.address)] = freeze.value;
+        }
+    }
+}
+
+fn parse_freeze(text: &str) -> Result<Freeze, String> {
+    let mut parts = text.splitn(3, char::is_whitespace).filter(|part| !part.is_empty());
+    let address = parts.next()
+        .and_then(parse_address)
+        .filter(|address| *address < 4096)
+        .ok_or(format!("Invalid address in {}", text))?;
+    let value = parts.next()
+        .and_then(parse_value)
+        .ok_or(format!("Invalid value in {}", text))?;
+    Ok(Freeze {
+        address,
+        value,
+        description: parts.next().unwrap_or("").trim().to_string(),
+    })
+}
+
+// A byte in decimal, or hex with 0x or $
+fn parse_value(text: &str) -> Option<u8> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// Finds the addresses of values such as lives or health, by narrowing down
+// all of RAM to the addresses which change in the same way as the value does
+pub struct Search {
+    snapshot: [u8; 4096],
+    candidates: Vec<u16>,
+}
+
+impl Search {
+    pub fn new(ram: &[u8; 4096]) -> Self {
+        Self {
+            snapshot: *ram,
+            candidates: (0..4096).collect(),
+        }
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    // Keep the candidates whose new value passes the comparison with their
+    // value at the last snapshot, then take a new snapshot
+    pub fn narrow(&mut self, ram: &[u8; 4096], comparison: Comparison) {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|address| {
+            let old = snapshot[usize::from(*address)];
+            let new = ram[usize::from(*address)];
+            match comparison {
+                Comparison::Equal => new == old,
+                Comparison::Changed => new != old,
+                Comparison::Increased => new > old,
+                Comparison::Decreased => new < old,
+                Comparison::Value(value) => new == value,
+            }
+        });
+        self.snapshot = *ram;
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Comparison {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value(u8),
+}
+
+// Run a cheat console command, printing the outcome
+pub fn run_command(command: &str, ram: &[u8; 4096], search: &mut Option<Search>, cheats: &mut Cheats, cheat_file: &str) {
+    let mut words = command.split_whitespace();
+    let comparison = match words.next() {
+        None => return,
+        Some("new") => {
+            *search = Some(Search::new(ram));
+            println!("Searching {} addresses", ram.len());
+            return;
+        },
+        Some("eq") => Comparison::Equal,
+        Some("ne") => Comparison::Changed,
+        Some("gt") => Comparison::Increased,
+        Some("lt") => Comparison::Decreased,
+        Some("list") => {
+            match search {
+                Some(search) => print_candidates(search, ram),
+                None => println!("No search, start one with new"),
+            }
+            return;
+        },
+        Some("freeze") => {
+            match parse_freeze(command.trim_start()["freeze".len()..].trim()) {
+                Ok(freeze) => {
+                    println!("Froze {:#05X} at {:#04X}", freeze.address, freeze.value);
+                    cheats.freeze(freeze);
+                },
+                Err(e) => println!("{}", e),
+            }
+            return;
+        },
+        Some("unfreeze") => {
+            match words.next().and_then(parse_address) {
+                Some(address) if cheats.unfreeze(address) => println!("Unfroze {:#05X}", address),
+                _ => println!("Not a frozen address"),
+            }
+            return;
+        },
+        Some("cheats") => {
+            if cheats.is_empty() {
+                println!("Nothing frozen");
+            }
+            for freeze in cheats.freezes() {
+                println!("{:#05X} = {:#04X} {}", freeze.address, freeze.value, freeze.description);
+            }
+            return;
+        },
+        Some("save") => {
+            match cheats.save(cheat_file) {
+                Ok(()) => println!("Wrote {} cheats to {}", cheats.freezes().len(), cheat_file),
+                Err(e) => println!("{}", e),
+            }
+            return;
+        },
+        Some(word) => match parse_value(word) {
+            Some(value) => Comparison::Value(value),
+            None => {
+                println!("{}", HELP);
+                return;
+            },
+        },
+    };
+
+    let search = search.get_or_insert_with(|| Search::new(ram));
+    search.narrow(ram, comparison);
+    print_candidates(search, ram);
+}
+
+fn print_candidates(search: &Search, ram: &[u8; 4096]) {
+    println!("{} candidates", search.candidates().len());
+    if search.candidates().len() <= MAX_LISTED {
+        for address in search.candidates() {
+            println!("    {:#05X} = {:#04X}", address, ram[usize::from(*address)]);
+        }
+    }
+}
+
+// Lines typed into the terminal, read without blocking the emulator
+pub fn console() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
@@ -0,0 +1,58 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::palette::Palette;
+
+// Defaults read from a file, which the command line overrides. Each line is a
+// setting such as "theme = lcd", or a comment starting with #.
+#[derive(Clone, Default)]
+pub struct Config {
+    pub palette: Option<Palette>,
+}
+
+impl Config {
+    // The given file, or the default one if there is one
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match Config::default_path() {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("{}:{}", path.display(), e))
+    }
+
+    // $XDG_CONFIG_HOME/rchip8/config, or ~/.config/rchip8/config
+    fn default_path() -> Option<PathBuf> {
+        let directory = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(directory.join("rchip8").join("config"))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=')
+                .ok_or(format!("{}: Expected a setting such as theme = lcd", number + 1))?;
+            let value = value.trim();
+
+            match key.trim() {
+                "theme" | "colors" => config.palette = Some(Palette::parse(value).map_err(|e| format!("{}: {}", number + 1, e))?),
+                key => return Err(format!("{}: Unknown setting {}", number + 1, key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::disassembler;
+use crate::op_code::OpCode;
+use crate::platform::Platform;
+
+pub const ROM_START: u16 = 0x200;
+
+// How control leaves an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    // Carries on to the next instruction
+    Next,
+    // Carries on to either the next instruction, or the one after it
+    Skip,
+    Jump(u16),
+    Call(u16),
+    // BNNN, whose target depends on V0 and so can't be known
+    IndirectJump(u16),
+    Return,
+    // SCHIP's 00FD
+    Exit,
+}
+
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub flow: Flow,
+}
+
+impl Instruction {
+    pub fn next_address(&self) -> u16 {
+        self.address + self.bytes.len() as u16
+    }
+
+    pub fn op_code(&self) -> OpCode {
+        OpCode::from_bytes(self.bytes[0], self.bytes[1])
+    }
+}
+
+// How control passes from one basic block to another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    // Falls through to the next instruction
+    Next,
+    Jump,
+    // A skip instruction which doesn't skip
+    NoSkip,
+    Skip,
+    // Calls a subroutine, and carries on after it returns
+    Call,
+}
+
+// A run of instructions which is only entered at the start, and only left at
+// the end (or by a call)
+pub struct BasicBlock {
+    pub start: u16,
+    // Addresses of the block's instructions, in order
+    pub instructions: Vec<u16>,
+    pub successors: Vec<(u16, Edge)>,
+}
+
+// The instructions reachable from the entry point, found by following jumps,
+// calls and skips rather than assuming every word is code
+pub struct Analysis {
+    pub rom_size: usize,
+    pub instructions: BTreeMap<u16, Instruction>,
+    pub jump_targets: BTreeSet<u16>,
+    pub call_targets: BTreeSet<u16>,
+    // Addresses loaded into I by ANNN or F000 NNNN
+    pub index_targets: BTreeSet<u16>,
+    // Addresses of BNNN instructions, whose targets are unknown
+    pub indirect_jumps: BTreeSet<u16>,
+}
+
+impl Analysis {
+    pub fn rom_end(&self) -> u16 {
+        ROM_START + self.rom_size as u16
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        address >= ROM_START && address < self.rom_end()
+    }
+
+    // Where a skip instruction goes when it skips, which is past the next
+    // instruction however long it is
+    pub fn skip_target(&self, instruction: &Instruction) -> u16 {
+        let next_address = instruction.next_address();
+        self.instructions.get(&next_address)
+            .map(Instruction::next_address)
+            .unwrap_or(next_address + 2)
+    }
+
+    pub fn basic_blocks(&self) -> BTreeMap<u16, BasicBlock> {
+        let mut leaders = BTreeSet::new();
+        leaders.insert(ROM_START);
+        leaders.extend(&self.jump_targets);
+        leaders.extend(&self.call_targets);
+        for instruction in self.instructions.values() {
+            match instruction.flow {
+                Flow::Skip => {
+                    leaders.insert(instruction.next_address());
+                    leaders.insert(self.skip_target(instruction));
+                },
+                Flow::Jump(_) | Flow::IndirectJump(_) | Flow::Return | Flow::Exit => {
+                    leaders.insert(instruction.next_address());
+                },
+                Flow::Next | Flow::Call(_) => {},
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for start in leaders.iter().filter(|address| self.instructions.contains_key(address)) {
+            let mut block = BasicBlock {
+                start: *start,
+                instructions: Vec::new(),
+                successors: Vec::new(),
+            };
+
+            let mut address = *start;
+            while let Some(instruction) = self.instructions.get(&address) {
+                block.instructions.push(address);
+                let next_address = instruction.next_address();
+                match instruction.flow {
+                    Flow::Next | Flow::Call(_) => {
+                        if let Flow::Call(target) = instruction.flow {
+                            block.successors.push((target, Edge::Call));
+                        }
+                        if leaders.contains(&next_address) && self.instructions.contains_key(&next_address) {
+                            block.successors.push((next_address, Edge::Next));
+                            break;
+                        }
+                    },
+                    Flow::Skip => {
+                        block.successors.push((next_address, Edge::NoSkip));
+                        block.successors.push((self.skip_target(instruction), Edge::Skip));
+                        break;
+                    },
+                    Flow::Jump(target) => {
+                        block.successors.push((target, Edge::Jump));
+                        break;
+                    },
+                    Flow::IndirectJump(_) | Flow::Return | Flow::Exit => break,
+                }
+                address = next_address;
+            }
+
+            blocks.insert(*start, block);
+        }
+
+        blocks
+    }
+
+    // The instruction which the address is part of, if any
+    pub fn instruction_covering(&self, address: u16) -> Option<&Instruction> {
+        self.instructions.range(..=address)
+            .next_back()
+            .map(|(_, instruction)| instruction)
+            .filter(|instruction| address < instruction.next_address())
+    }
+}
+
+pub fn analyze(rom: &[u8], platform: Platform) -> Analysis {
+    let mut analysis = Analysis {
+        rom_size: rom.len().min(0x1000 - usize::from(ROM_START)),
+        instructions: BTreeMap::new(),
+        jump_targets: BTreeSet::new(),
+        call_targets: BTreeSet::new(),
+        index_targets: BTreeSet::new(),
+        indirect_jumps: BTreeSet::new(),
+    };
+
+    let mut pending = vec![ROM_START];
+    while let Some(address) = pending.pop() {
+        // Stop at anything outside the rom, or already disassembled. Jumps
+        // into the middle of an instruction are left for the caller to spot.
+        if !analysis.contains(address) || analysis.instruction_covering(address).is_some() {
+            continue;
+        }
+
+        let offset = usize::from(address - ROM_START);
+        let decoded = disassembler::decode(&rom[offset..analysis.rom_size], platform);
+        if decoded.mnemonic.is_none() {
+            continue;
+        }
+
+        // Overlapping instructions can't both be code
+        let overlaps = analysis.instructions
+            .range(address + 1..address + decoded.length as u16)
+            .next()
+            .is_some();
+        if overlaps {
+            continue;
+        }
+
+        let bytes = rom[offset..offset + decoded.length].to_vec();
+        let op_code = OpCode::from_bytes(bytes[0], bytes[1]);
+        let flow = flow_of(&op_code, platform);
+        let next_address = address + decoded.length as u16;
+
+        match flow {
+            Flow::Next | Flow::Call(_) => pending.push(next_address),
+            Flow::Skip => {
+                // Skipping over XO-CHIP's F000 NNNN skips all 4 bytes
+                let next_offset = usize::from(next_address - ROM_START);
+                let skipped = disassembler::decode(rom.get(next_offset..).unwrap_or(&[]), platform);
+                pending.push(next_address + skipped.length.max(2) as u16);
+                pending.push(next_address);
+            },
+            Flow::Jump(_) | Flow::IndirectJump(_) | Flow::Return | Flow::Exit => {},
+        }
+
+        match flow {
+            Flow::Jump(target) => {
+                analysis.jump_targets.insert(target);
+                pending.push(target);
+            },
+            Flow::Call(target) => {
+                analysis.call_targets.insert(target);
+                pending.push(target);
+            },
+            Flow::IndirectJump(_) => {
+                analysis.indirect_jumps.insert(address);
+            },
+            _ => {},
+        }
+
+        if op_code.op == 0xA {
+            analysis.index_targets.insert(op_code.nnn);
+        } else if decoded.length == 4 {
+            analysis.index_targets.insert((u16::from(bytes[2]) << 8) | u16::from(bytes[3]));
+        }
+
+        analysis.instructions.insert(address, Instruction {
+            address,
+            bytes,
+            flow,
+        });
+    }
+
+    analysis
+}
+
+fn flow_of(op_code: &OpCode, platform: Platform) -> Flow {
+    match op_code.pattern() {
+        "00EE" => Flow::Return,
+        "0NNN" if platform >= Platform::Schip && op_code.nnn == 0x0FD => Flow::Exit,
+        "1NNN" => Flow::Jump(op_code.nnn),
+        "2NNN" => Flow::Call(op_code.nnn),
+        "3XNN" | "4XNN" | "5XY0" | "9XY0" | "EX9E" | "EXA1" => Flow::Skip,
+        "BNNN" => Flow::IndirectJump(op_code.nnn),
+        _ => Flow::Next,
+    }
+}
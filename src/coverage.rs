@@ -0,0 +1,154 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::symbols::Symbols;
+
+// Ways in which a byte of ram can be accessed
+pub const FETCHED: u8 = 0b0001; // Fetched as an instruction by next_op
+pub const SPRITE: u8 = 0b0010; // Read as sprite data by DXYN
+pub const DATA: u8 = 0b0100; // Read as data by FX65
+pub const WRITTEN: u8 = 0b1000; // Written by FX33 or FX55
+
+const ROM_START: usize = 0x200;
+
+// Memory map images have one cell per byte, with this many bytes per row
+const MAP_BYTES_PER_ROW: usize = 64;
+const MAP_CELL_SIZE: usize = 8;
+
+// Tracks every access to ram, so that code can be told apart from data
+#[derive(Debug)]
+pub struct Coverage {
+    flags: [u8; 4096],
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self {
+            flags: [0; 4096],
+        }
+    }
+
+    pub fn mark(&mut self, address: usize, access: u8) {
+        if address < self.flags.len() {
+            self.flags[address] |= access;
+        }
+    }
+
+    pub fn has(&self, address: usize, access: u8) -> bool {
+        self.flags[address] & access != 0
+    }
+
+    // Fraction of the rom's bytes accessed in each way, as a one line summary
+    pub fn summary(&self, rom_size: usize) -> String {
+        let rom = &self.flags[ROM_START..(ROM_START + rom_size).min(4096)];
+        let count = |access: u8| rom.iter().filter(|flags| *flags & access != 0).count();
+        let percent = |count: usize| 100.0 * count as f64 / rom.len().max(1) as f64;
+
+        let untouched = rom.iter().filter(|flags| **flags == 0).count();
+        format!(
+            "Code: {} ({:.1}%), Sprite: {} ({:.1}%), Data: {} ({:.1}%), Written: {} ({:.1}%), Untouched: {} ({:.1}%) of {} rom bytes",
+            count(FETCHED), percent(count(FETCHED)),
+            count(SPRITE), percent(count(SPRITE)),
+            count(DATA), percent(count(DATA)),
+            count(WRITTEN), percent(count(WRITTEN)),
+            untouched, percent(untouched),
+            rom.len(),
+        )
+    }
+
+    // Write the coverage of the rom, either as a memory map image if the path
+    // ends in .ppm, or as an annotated listing otherwise
+    pub fn write(&self, path: &str, ram: &[u8; 4096], rom_size: usize, symbols: &Symbols) -> io::Result<()> {
+        let is_image = Path::new(path).extension().is_some_and(|extension| extension == "ppm");
+        if is_image {
+            fs::write(path, self.memory_map(rom_size))
+        } else {
+            fs::write(path, self.listing(ram, rom_size, symbols))
+        }
+    }
+
+    fn access_string(&self, address: usize) -> String {
+        let flag = |access: u8, c: char| if self.has(address, access) { c } else { '-' };
+        [
+            flag(FETCHED, 'C'),
+            flag(SPRITE, 'S'),
+            flag(DATA, 'D'),
+            flag(WRITTEN, 'W'),
+        ].iter().collect()
+    }
+
+    fn listing(&self, ram: &[u8; 4096], rom_size: usize, symbols: &Symbols) -> String {
+        let mut listing = String::new();
+        listing.push_str("; C = fetched as code, S = read as sprite, D = read as data, W = written\n");
+        listing.push_str(&format!("; {}\n", self.summary(rom_size)));
+
+        let end = (ROM_START + rom_size).min(4096);
+        let mut address = ROM_START;
+        while address < end {
+            if let Some(label) = symbols.label_at(address as u16) {
+                listing.push_str(&format!("{}:\n", label));
+            }
+
+            // Instructions are listed as whole words, everything else byte by byte
+            let is_instruction = address + 1 < end
+                && self.has(address, FETCHED)
+                && self.has(address + 1, FETCHED);
+            if is_instruction {
+                let word = (u16::from(ram[address]) << 8) | u16::from(ram[address + 1]);
+                listing.push_str(&format!(
+                    "{:#05X}  {:04X}  {}\n",
+                    address, word, self.access_string(address),
+                ));
+                address += 2;
+            } else {
+                let byte = ram[address];
+                let bits: String = (0..8)
+                    .map(|bit| if byte & (1 << (7 - bit)) != 0 { '#' } else { '.' })
+                    .collect();
+                listing.push_str(&format!(
+                    "{:#05X}  {:02X}    {}  {}\n",
+                    address, byte, self.access_string(address), bits,
+                ));
+                address += 1;
+            }
+        }
+
+        listing
+    }
+
+    fn color(&self, address: usize, rom_size: usize) -> [u8; 3] {
+        let in_rom = address >= ROM_START && address < ROM_START + rom_size;
+        if self.has(address, FETCHED) && self.has(address, WRITTEN) {
+            [255, 0, 255] // Self modifying code
+        } else if self.has(address, FETCHED) {
+            [64, 96, 255]
+        } else if self.has(address, SPRITE) {
+            [0, 224, 0]
+        } else if self.has(address, DATA) {
+            [255, 224, 0]
+        } else if self.has(address, WRITTEN) {
+            [255, 32, 32]
+        } else if in_rom {
+            [64, 64, 64]
+        } else {
+            [0, 0, 0]
+        }
+    }
+
+    // A binary PPM of the whole of ram
+    fn memory_map(&self, rom_size: usize) -> Vec<u8> {
+        let width = MAP_BYTES_PER_ROW * MAP_CELL_SIZE;
+        let height = (self.flags.len() / MAP_BYTES_PER_ROW) * MAP_CELL_SIZE;
+
+        let mut image = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        for y in 0..height {
+            for x in 0..width {
+                let address = (y / MAP_CELL_SIZE) * MAP_BYTES_PER_ROW + x / MAP_CELL_SIZE;
+                image.extend_from_slice(&self.color(address, rom_size));
+            }
+        }
+
+        image
+    }
+}
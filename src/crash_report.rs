@@ -0,0 +1,58 @@
+use crate::disassembler;
+use crate::op_code::OpCode;
+use crate::platform::Platform;
+use crate::state::{ExecutionError, State};
+use crate::symbols::Symbols;
+
+// How many instructions either side of the faulting one to disassemble
+const CONTEXT_INSTRUCTIONS: u16 = 8;
+
+// A readable description of the emulator's state when execution failed at pc
+pub fn build(state: &State, pc: u16, error: &ExecutionError, symbols: &Symbols, platform: Platform) -> String {
+    let mut report = String::new();
+
+    report.push_str(&format!("=== Execution failed at {:#05X} ({})\n", pc, symbols.resolve(pc)));
+    report.push_str(&format!("{}\n", error));
+
+    report.push_str("\n=== Disassembly\n");
+    let ram = state.get_ram();
+    let start = pc.saturating_sub(2 * CONTEXT_INSTRUCTIONS);
+    let end = pc.saturating_add(2 * CONTEXT_INSTRUCTIONS).min(4094);
+    for address in (start..=end).step_by(2) {
+        let marker = if address == pc { "=>" } else { "  " };
+        report.push_str(&format!("{} {}\n", marker, instruction_line(ram, address, symbols, platform)));
+    }
+
+    report.push_str("\n=== Call stack\n");
+    report.push_str(&format!("#0 {}\n", symbols.resolve(pc)));
+    for (depth, return_address) in state.get_stack().iter().rev().enumerate() {
+        // The call itself is the instruction before the return address
+        let call_site = return_address.wrapping_sub(2);
+        report.push_str(&format!("#{} {} (called from {:#05X})\n", depth + 1, symbols.resolve(call_site), call_site));
+    }
+
+    report.push_str("\n=== Registers\n");
+    for (index, value) in state.get_v().iter().enumerate() {
+        let separator = if index % 4 == 3 { "\n" } else { "  " };
+        report.push_str(&format!("V{:X} = {:#04X}{}", index, value, separator));
+    }
+    report.push_str(&format!("I  = {:#05X} ({})\n", state.get_i(), symbols.resolve(state.get_i())));
+    report.push_str(&format!("Delay timer = {}  Sound timer = {}\n", state.get_delay_timer(), state.get_sound_timer()));
+
+    report.push_str("\n=== Last executed instructions\n");
+    for (address, word) in state.get_history() {
+        let op_code = OpCode::from_bytes((word >> 8) as u8, *word as u8);
+        let mnemonic = disassembler::mnemonic(&op_code, platform).unwrap_or_else(|| "???".to_string());
+        report.push_str(&format!("{:<24} {:#05X}  {:04X}  {}\n", symbols.resolve(*address), address, word, mnemonic));
+    }
+
+    report
+}
+
+fn instruction_line(ram: &[u8; 4096], address: u16, symbols: &Symbols, platform: Platform) -> String {
+    let byte1 = ram[usize::from(address)];
+    let byte2 = ram[usize::from(address) + 1];
+    let op_code = OpCode::from_bytes(byte1, byte2);
+    let mnemonic = disassembler::mnemonic(&op_code, platform).unwrap_or_else(|| "???".to_string());
+    format!("{:<24} {:#05X}  {:02X}{:02X}  {}", symbols.resolve(address), address, byte1, byte2, mnemonic)
+}
@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+
+use crate::control_flow::{self, Flow, Instruction};
+use crate::op_code::OpCode;
+use crate::platform::Platform;
+
+const ROM_START: usize = 0x200;
+
+// A single instruction, or data if it doesn't decode on the platform
+pub struct Decoded {
+    pub length: usize,
+    pub mnemonic: Option<String>,
+}
+
+// Decode the instruction at the start of bytes. Everything is 2 bytes long,
+// apart from XO-CHIP's F000 NNNN and a trailing odd byte.
+pub fn decode(bytes: &[u8], platform: Platform) -> Decoded {
+    if bytes.len() < 2 {
+        return Decoded {
+            length: bytes.len(),
+            mnemonic: None,
+        };
+    }
+
+    if platform == Platform::XoChip && bytes[0] == 0xF0 && bytes[1] == 0x00 {
+        return match bytes.get(2..4) {
+            Some(address) => Decoded {
+                length: 4,
+                mnemonic: Some(format!("LD I, LONG {:#06X}", (u16::from(address[0]) << 8) | u16::from(address[1]))),
+            },
+            None => Decoded {
+                length: 2,
+                mnemonic: None,
+            },
+        };
+    }
+
+    Decoded {
+        length: 2,
+        mnemonic: mnemonic(&OpCode::from_bytes(bytes[0], bytes[1]), platform),
+    }
+}
+
+// Cowgod style assembly for an instruction, e.g. "LD V1, 0x20", or None if
+// it isn't a valid instruction on the platform
+pub fn mnemonic(op_code: &OpCode, platform: Platform) -> Option<String> {
+    let x = op_code.x;
+    let y = op_code.y;
+    let n = op_code.n;
+    let nn = op_code.nn;
+    let nnn = op_code.nnn;
+
+    if platform >= Platform::XoChip {
+        let mnemonic = match (op_code.op, x, y, n) {
+            (0x0, 0x0, 0xD, _) => Some(format!("SCU {}", n)),
+            (0x5, _, _, 0x2) => Some(format!("SAVE V{:X}, V{:X}", x, y)),
+            (0x5, _, _, 0x3) => Some(format!("LOAD V{:X}, V{:X}", x, y)),
+            (0xF, _, 0x0, 0x1) => Some(format!("PLANE {}", x)),
+            (0xF, 0x0, 0x0, 0x2) => Some("AUDIO".to_string()),
+            (0xF, _, 0x3, 0xA) => Some(format!("PITCH V{:X}", x)),
+            _ => None,
+        };
+        if mnemonic.is_some() {
+            return mnemonic;
+        }
+    }
+
+    if platform >= Platform::Schip {
+        let mnemonic = match (op_code.op, x, y, n) {
+            (0x0, 0x0, 0xC, _) => Some(format!("SCD {}", n)),
+            (0x0, 0x0, 0xF, 0xB) => Some("SCR".to_string()),
+            (0x0, 0x0, 0xF, 0xC) => Some("SCL".to_string()),
+            (0x0, 0x0, 0xF, 0xD) => Some("EXIT".to_string()),
+            (0x0, 0x0, 0xF, 0xE) => Some("LOW".to_string()),
+            (0x0, 0x0, 0xF, 0xF) => Some("HIGH".to_string()),
+            (0xF, _, 0x3, 0x0) => Some(format!("LD HF, V{:X}", x)),
+            (0xF, _, 0x7, 0x5) => Some(format!("LD R, V{:X}", x)),
+            (0xF, _, 0x8, 0x5) => Some(format!("LD V{:X}, R", x)),
+            _ => None,
+        };
+        if mnemonic.is_some() {
+            return mnemonic;
+        }
+    }
+
+    let mnemonic = match op_code.pattern() {
+        "00E0" => "CLS".to_string(),
+        "00EE" => "RET".to_string(),
+        // Machine code routines only exist on the original interpreter
+        "0NNN" if platform == Platform::Chip8 => format!("SYS {:#05X}", nnn),
+        "1NNN" => format!("JP {:#05X}", nnn),
+        "2NNN" => format!("CALL {:#05X}", nnn),
+        "3XNN" => format!("SE V{:X}, {:#04X}", x, nn),
+        "4XNN" => format!("SNE V{:X}, {:#04X}", x, nn),
+        "5XY0" => format!("SE V{:X}, V{:X}", x, y),
+        "6XNN" => format!("LD V{:X}, {:#04X}", x, nn),
+        "7XNN" => format!("ADD V{:X}, {:#04X}", x, nn),
+        "8XY0" => format!("LD V{:X}, V{:X}", x, y),
+        "8XY1" => format!("OR V{:X}, V{:X}", x, y),
+        "8XY2" => format!("AND V{:X}, V{:X}", x, y),
+        "8XY3" => format!("XOR V{:X}, V{:X}", x, y),
+        "8XY4" => format!("ADD V{:X}, V{:X}", x, y),
+        "8XY5" => format!("SUB V{:X}, V{:X}", x, y),
+        "8XY6" => format!("SHR V{:X}, V{:X}", x, y),
+        "8XY7" => format!("SUBN V{:X}, V{:X}", x, y),
+        "8XYE" => format!("SHL V{:X}, V{:X}", x, y),
+        "9XY0" => format!("SNE V{:X}, V{:X}", x, y),
+        "ANNN" => format!("LD I, {:#05X}", nnn),
+        "BNNN" => format!("JP V0, {:#05X}", nnn),
+        "CXNN" => format!("RND V{:X}, {:#04X}", x, nn),
+        "DXYN" => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        "EX9E" => format!("SKP V{:X}", x),
+        "EXA1" => format!("SKNP V{:X}", x),
+        "FX07" => format!("LD V{:X}, DT", x),
+        "FX0A" => format!("LD V{:X}, K", x),
+        "FX15" => format!("LD DT, V{:X}", x),
+        "FX18" => format!("LD ST, V{:X}", x),
+        "FX1E" => format!("ADD I, V{:X}", x),
+        "FX29" => format!("LD F, V{:X}", x),
+        "FX33" => format!("LD B, V{:X}", x),
+        "FX55" => format!("LD [I], V{:X}", x),
+        "FX65" => format!("LD V{:X}, [I]", x),
+        _ => return None,
+    };
+
+    Some(mnemonic)
+}
+
+// Data directive for bytes which aren't an instruction
+pub fn data_directive(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+    format!("db {}", bytes.join(", "))
+}
+
+// Disassemble every word of the rom in turn, one line per instruction
+pub fn linear_sweep(rom: &[u8], platform: Platform) -> String {
+    let mut listing = String::new();
+
+    let mut offset = 0;
+    while offset < rom.len() {
+        let decoded = decode(&rom[offset..], platform);
+        let bytes = &rom[offset..offset + decoded.length];
+
+        let raw: String = bytes.chunks(2)
+            .map(|word| word.iter().map(|byte| format!("{:02X}", byte)).collect::<String>())
+            .collect::<Vec<String>>()
+            .join(" ");
+        let text = match decoded.mnemonic {
+            Some(mnemonic) => mnemonic,
+            None => format!("{:<20}; data", data_directive(bytes)),
+        };
+        listing.push_str(&format!("{:#05X}  {:<9}  {}\n", ROM_START + offset, raw, text));
+
+        offset += decoded.length;
+    }
+
+    listing
+}
+
+// Disassemble every word of the rom in turn as source for the assembler,
+// with the address and raw bytes of each line in a comment
+pub fn source_listing(rom: &[u8], platform: Platform) -> String {
+    let mut listing = String::new();
+
+    let mut offset = 0;
+    while offset < rom.len() {
+        let decoded = decode(&rom[offset..], platform);
+        let bytes = &rom[offset..offset + decoded.length];
+
+        let raw: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text = decoded.mnemonic.unwrap_or_else(|| data_directive(bytes));
+        listing.push_str(&format!("    {:<24}; {:#05X}  {}\n", text, ROM_START + offset, raw));
+
+        offset += decoded.length;
+    }
+
+    listing
+}
+
+// Octo statement for an instruction, with addresses shown as labels where
+// possible, or None if Octo has no equivalent
+fn octo_statement(instruction: &Instruction, platform: Platform, labels: &BTreeMap<u16, String>) -> Option<String> {
+    let target = |address: u16| match labels.get(&address) {
+        Some(label) => label.clone(),
+        None => format!("{:#05X}", address),
+    };
+
+    let bytes = &instruction.bytes;
+    if bytes.len() == 4 {
+        return Some(format!("i := long {}", target((u16::from(bytes[2]) << 8) | u16::from(bytes[3]))));
+    }
+
+    let op_code = instruction.op_code();
+    let x = op_code.x;
+    let y = op_code.y;
+    let n = op_code.n;
+    let nn = op_code.nn;
+
+    if platform >= Platform::XoChip {
+        let statement = match (op_code.op, x, y, n) {
+            (0x0, 0x0, 0xD, _) => Some(format!("scroll-up {}", n)),
+            (0x5, _, _, 0x2) => Some(format!("save v{:x} - v{:x}", x, y)),
+            (0x5, _, _, 0x3) => Some(format!("load v{:x} - v{:x}", x, y)),
+            (0xF, _, 0x0, 0x1) => Some(format!("plane {}", x)),
+            (0xF, 0x0, 0x0, 0x2) => Some("audio".to_string()),
+            (0xF, _, 0x3, 0xA) => Some(format!("pitch := v{:x}", x)),
+            _ => None,
+        };
+        if statement.is_some() {
+            return statement;
+        }
+    }
+
+    if platform >= Platform::Schip {
+        let statement = match (op_code.op, x, y, n) {
+            (0x0, 0x0, 0xC, _) => Some(format!("scroll-down {}", n)),
+            (0x0, 0x0, 0xF, 0xB) => Some("scroll-right".to_string()),
+            (0x0, 0x0, 0xF, 0xC) => Some("scroll-left".to_string()),
+            (0x0, 0x0, 0xF, 0xD) => Some("exit".to_string()),
+            (0x0, 0x0, 0xF, 0xE) => Some("lores".to_string()),
+            (0x0, 0x0, 0xF, 0xF) => Some("hires".to_string()),
+            (0xF, _, 0x3, 0x0) => Some(format!("i := bighex v{:x}", x)),
+            (0xF, _, 0x7, 0x5) => Some(format!("saveflags v{:x}", x)),
+            (0xF, _, 0x8, 0x5) => Some(format!("loadflags v{:x}", x)),
+            _ => None,
+        };
+        if statement.is_some() {
+            return statement;
+        }
+    }
+
+    // Octo's conditions say when the next statement runs, whereas the skip
+    // instructions say when it doesn't, so every condition is inverted
+    let statement = match op_code.pattern() {
+        "00E0" => "clear".to_string(),
+        "00EE" => "return".to_string(),
+        "1NNN" => format!("jump {}", target(op_code.nnn)),
+        "2NNN" => match labels.get(&op_code.nnn) {
+            Some(label) => label.clone(),
+            None => format!(":call {:#05X}", op_code.nnn),
+        },
+        "3XNN" => format!("if v{:x} != {:#04X} then", x, nn),
+        "4XNN" => format!("if v{:x} == {:#04X} then", x, nn),
+        "5XY0" => format!("if v{:x} != v{:x} then", x, y),
+        "6XNN" => format!("v{:x} := {:#04X}", x, nn),
+        "7XNN" => format!("v{:x} += {:#04X}", x, nn),
+        "8XY0" => format!("v{:x} := v{:x}", x, y),
+        "8XY1" => format!("v{:x} |= v{:x}", x, y),
+        "8XY2" => format!("v{:x} &= v{:x}", x, y),
+        "8XY3" => format!("v{:x} ^= v{:x}", x, y),
+        "8XY4" => format!("v{:x} += v{:x}", x, y),
+        "8XY5" => format!("v{:x} -= v{:x}", x, y),
+        "8XY6" => format!("v{:x} >>= v{:x}", x, y),
+        "8XY7" => format!("v{:x} =- v{:x}", x, y),
+        "8XYE" => format!("v{:x} <<= v{:x}", x, y),
+        "9XY0" => format!("if v{:x} == v{:x} then", x, y),
+        "ANNN" => format!("i := {}", target(op_code.nnn)),
+        "BNNN" => format!("jump0 {}", target(op_code.nnn)),
+        "CXNN" => format!("v{:x} := random {:#04X}", x, nn),
+        "DXYN" => format!("sprite v{:x} v{:x} {}", x, y, n),
+        "EX9E" => format!("if v{:x} -key then", x),
+        "EXA1" => format!("if v{:x} key then", x),
+        "FX07" => format!("v{:x} := delay", x),
+        "FX0A" => format!("v{:x} := key", x),
+        "FX15" => format!("delay := v{:x}", x),
+        "FX18" => format!("buzzer := v{:x}", x),
+        "FX1E" => format!("i += v{:x}", x),
+        "FX29" => format!("i := hex v{:x}", x),
+        "FX33" => format!("bcd v{:x}", x),
+        "FX55" => format!("save v{:x}", x),
+        "FX65" => format!("load v{:x}", x),
+        _ => return None,
+    };
+
+    Some(statement)
+}
+
+fn octo_bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+    bytes.join(" ")
+}
+
+// Follow the flow of control from the entry point, and emit labelled Octo
+// source which reassembles to exactly the same rom. Bytes which are never
+// reached are emitted as data.
+pub fn recursive_descent(rom: &[u8], platform: Platform) -> String {
+    let analysis = control_flow::analyze(rom, platform);
+
+    // Labels can only go at the start of an instruction, or within data
+    let can_label = |address: u16| {
+        analysis.contains(address)
+            && (analysis.instructions.contains_key(&address) || analysis.instruction_covering(address).is_none())
+    };
+
+    let mut labels = BTreeMap::new();
+    labels.insert(ROM_START as u16, "main".to_string());
+    let named_targets = [
+        (&analysis.call_targets, "sub"),
+        (&analysis.jump_targets, "label"),
+        (&analysis.index_targets, "data"),
+    ];
+    for (targets, prefix) in named_targets.iter() {
+        for address in targets.iter().filter(|address| can_label(**address)) {
+            labels.entry(*address).or_insert_with(|| format!("{}_{:03X}", prefix, address));
+        }
+    }
+
+    let mut source = String::new();
+    source.push_str(&format!("# Disassembled by rchip8 for {}\n", platform.name()));
+    for address in &analysis.indirect_jumps {
+        source.push_str(&format!("# The target of jump0 at {:#05X} is unknown\n", address));
+    }
+
+    let rom_end = analysis.rom_end();
+    let mut address = ROM_START as u16;
+    while address < rom_end {
+        if let Some(label) = labels.get(&address) {
+            source.push_str(&format!("\n: {}\n", label));
+        }
+
+        if let Some(instruction) = analysis.instructions.get(&address) {
+            // A label can't come between a condition and its statement
+            let splits_condition = instruction.flow == Flow::Skip
+                && labels.contains_key(&instruction.next_address());
+            let statement = octo_statement(instruction, platform, &labels)
+                .filter(|_| !splits_condition);
+
+            match statement {
+                Some(statement) => source.push_str(&format!("\t{}\n", statement)),
+                None => source.push_str(&format!("\t{}\n", octo_bytes(&instruction.bytes))),
+            }
+            address = instruction.next_address();
+            continue;
+        }
+
+        // Data runs up to the next instruction or label, 8 bytes per line
+        let start = address;
+        address += 1;
+        while address < rom_end
+            && address - start < 8
+            && !labels.contains_key(&address)
+            && !analysis.instructions.contains_key(&address) {
+            address += 1;
+        }
+
+        let offset = usize::from(start - ROM_START as u16);
+        let bytes = &rom[offset..offset + usize::from(address - start)];
+        source.push_str(&format!("\t{}\n", octo_bytes(bytes)));
+    }
+
+    source
+}
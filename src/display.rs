@@ -4,53 +4,101 @@ use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::Sdl;
-use sdl2::video::Window;
+use sdl2::video::{FullscreenType, Window, WindowPos};
+
+use crate::font;
+use crate::palette::Palette;
+use crate::renderer::Renderer;
+
+// How much bigger than the font text is drawn, and the space around each line
+const TEXT_SCALE: u32 = 2;
+const LINE_SPACING: u32 = 2;
+const TEXT_MARGIN: u32 = 4;
+
+// The colour of the bars either side of the screen when the window isn't 2:1
+const LETTERBOX_COLOR: Color = Color::RGB(0, 0, 0);
 
 pub struct Display {
     canvas: Canvas<Window>,
-    scale: u32,
+    // Whether pixels are only ever drawn a whole number of window pixels big,
+    // leaving wider bars around the screen
+    integer_scale: bool,
     background_color: Color,
     foreground_color: Color,
+    // How many frames a pixel takes to fade out after being turned off, like
+    // a CRT's phosphor, which hides the flicker of sprites being redrawn
+    persistence: u32,
+    // How lit each pixel is, from 0 to 1
+    intensity: [[f32; 32]; 64],
 }
 
 impl Display {
-    pub fn new(sdl_context: &Sdl) -> Self {
+    // A window scale times the size of the screen, which can be resized
+    pub fn new(sdl_context: &Sdl, palette: &Palette, scale: u32, integer_scale: bool) -> Self {
         let video_subsystem = sdl_context.video().unwrap();
 
-        let scale = 10;
-
-        let window = video_subsystem.window("rust-sdl2 demo", 64 * scale, 32 * scale)
+        let mut window = video_subsystem.window("rchip8", 64 * scale, 32 * scale)
             .position_centered()
+            .resizable()
             .build()
             .unwrap();
+        window.set_minimum_size(64, 32).expect("Failed to set window size");
 
         let canvas = window.into_canvas().build().unwrap();
 
-        Self {
+        let mut display = Self {
             canvas,
-            scale: 10,
+            integer_scale,
             background_color: Color::RGB(0, 0, 0),
             foreground_color: Color::RGB(255, 255, 255),
-        }
+            persistence: 0,
+            intensity: [[0.0; 32]; 64],
+        };
+        display.set_palette(palette);
+        display
     }
 
-    pub fn draw_canvas(self: &mut Display, frame_buffer: &[[bool; 32]; 64]) {
-        self.canvas.set_draw_color(self.background_color);
+    pub fn center(&mut self) {
+        self.canvas.window_mut().set_position(WindowPos::Centered, WindowPos::Centered);
+    }
+
+    // 0 or 1 draws each frame as it is
+    pub fn set_persistence(&mut self, frames: u32) {
+        self.persistence = frames;
+    }
+
+    // Turn every pixel straight off, for a new game
+    pub fn clear_persistence(&mut self) {
+        self.intensity = [[0.0; 32]; 64];
+    }
+
+    // Draw the last frame again, such as after the window is resized, without
+    // fading it any further
+    pub fn redraw(&mut self) {
+        self.canvas.set_draw_color(LETTERBOX_COLOR);
         self.canvas.clear();
 
-        self.canvas.set_draw_color(self.foreground_color);
-        for (x, col) in frame_buffer.iter().enumerate() {
-            for (y, pixel) in col.iter().enumerate() {
-                if *pixel {
-                    let x = ((x as u32) * self.scale) as i32;
-                    let y = ((y as u32) * self.scale) as i32;
-                    let width = self.scale;
-                    let height = self.scale;
+        let screen = self.screen_area();
+        self.canvas.set_draw_color(self.background_color);
+        self.canvas.fill_rect(screen).expect("Failed to draw screen");
+
+        // Pixels may not all be the same size when the scale isn't a whole
+        // number, so each one runs up to where the next starts
+        let column_edge = |x: usize| screen.x() + (x as u32 * screen.width() / 64) as i32;
+        let row_edge = |y: usize| screen.y() + (y as u32 * screen.height() / 32) as i32;
+
+        for (x, col) in self.intensity.iter().enumerate() {
+            for (y, intensity) in col.iter().enumerate() {
+                if *intensity > 0.0 {
+                    let color = mix(self.background_color, self.foreground_color, *intensity);
+                    self.canvas.set_draw_color(color);
+                    let left = column_edge(x);
+                    let top = row_edge(y);
                     self.canvas.fill_rect(Rect::new(
-                        x,
-                        y,
-                        width,
-                        height,
+                        left,
+                        top,
+                        (column_edge(x + 1) - left) as u32,
+                        (row_edge(y + 1) - top) as u32,
                     )).expect("Failed to draw pixel");
                 }
             }
@@ -58,4 +106,109 @@ impl Display {
 
         self.canvas.present();
     }
+
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(fullscreen).expect("Failed to switch to fullscreen");
+    }
+
+    // Where the screen goes in the window: as big as fits at 2:1, or the
+    // biggest whole number of times the resolution, centred between bars
+    fn screen_area(&self) -> Rect {
+        let (window_width, window_height) = self.canvas.output_size().expect("Failed to get window size");
+        let (width, height) = if self.integer_scale {
+            let scale = (window_width / 64).min(window_height / 32).max(1);
+            (64 * scale, 32 * scale)
+        } else {
+            let height = window_height.min(window_width / 2).max(1);
+            (2 * height, height)
+        };
+        Rect::new(
+            (window_width as i32 - width as i32) / 2,
+            (window_height as i32 - height as i32) / 2,
+            width,
+            height,
+        )
+    }
+
+    // How many characters fit across the window, and how many lines fit down it
+    pub fn text_size(&self) -> (usize, usize) {
+        let (width, height) = self.canvas.output_size().expect("Failed to get window size");
+        let columns = (width - 2 * TEXT_MARGIN) / ((font::WIDTH + 1) * TEXT_SCALE);
+        let rows = (height - 2 * TEXT_MARGIN) / ((font::HEIGHT + LINE_SPACING) * TEXT_SCALE);
+        (columns as usize, rows as usize)
+    }
+
+    // Fill the window with lines of text, with one line optionally highlighted
+    pub fn draw_text(&mut self, lines: &[String], highlighted: Option<usize>) {
+        self.canvas.set_draw_color(self.background_color);
+        self.canvas.clear();
+
+        let line_height = (font::HEIGHT + LINE_SPACING) * TEXT_SCALE;
+        for (row, line) in lines.iter().enumerate() {
+            let top = (TEXT_MARGIN + row as u32 * line_height) as i32;
+            let text_color = if highlighted == Some(row) {
+                let width = self.canvas.output_size().expect("Failed to get window size").0;
+                self.canvas.set_draw_color(self.foreground_color);
+                self.canvas.fill_rect(Rect::new(0, top, width, line_height)).expect("Failed to draw highlight");
+                self.background_color
+            } else {
+                self.foreground_color
+            };
+
+            self.canvas.set_draw_color(text_color);
+            for (column, c) in line.chars().enumerate() {
+                let left = TEXT_MARGIN + column as u32 * (font::WIDTH + 1) * TEXT_SCALE;
+                for (x, bits) in font::glyph(c).iter().enumerate() {
+                    for y in 0..font::HEIGHT {
+                        if bits & (1 << y) != 0 {
+                            self.canvas.fill_rect(Rect::new(
+                                (left + x as u32 * TEXT_SCALE) as i32,
+                                top + ((LINE_SPACING / 2 + y) * TEXT_SCALE) as i32,
+                                TEXT_SCALE,
+                                TEXT_SCALE,
+                            )).expect("Failed to draw text");
+                        }
+                    }
+                }
+            }
+        }
+
+        self.canvas.present();
+    }
+}
+
+impl Renderer for Display {
+    // Each call is one emulated frame, which fades the pixels turned off
+    fn draw_frame(&mut self, frame_buffer: &[[bool; 32]; 64]) {
+        let fade = 1.0 / self.persistence.max(1) as f32;
+        for (x, col) in frame_buffer.iter().enumerate() {
+            for (y, pixel) in col.iter().enumerate() {
+                let intensity = &mut self.intensity[x][y];
+                *intensity = if *pixel { 1.0 } else { (*intensity - fade).max(0.0) };
+            }
+        }
+
+        self.redraw();
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.canvas.window_mut().set_title(title).expect("Failed to set title");
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        let (background, fill) = (palette.background, palette.fill);
+        self.background_color = Color::RGB(background.0, background.1, background.2);
+        self.foreground_color = Color::RGB(fill.0, fill.1, fill.2);
+    }
+}
+
+// Part of the way from one colour to another
+fn mix(from: Color, to: Color, amount: f32) -> Color {
+    let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+    Color::RGB(channel(from.r, to.r), channel(from.g, to.g), channel(from.b, to.b))
 }
@@ -0,0 +1,148 @@
+// Octo cartridges are 128x64, so anything much bigger isn't one
+const MAX_FRAME_SIDE: usize = 256;
+// Enough frames for the largest Octo program
+const MAX_PIXELS: usize = 16 * 1024 * 1024;
+
+// Just enough GIF to read the images in Octo cartridges: the palette indices
+// of every frame, in the order they are stored
+pub fn decode_frames(data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    if !data.starts_with(b"GIF87a") && !data.starts_with(b"GIF89a") {
+        return Err("Not a GIF".to_string());
+    }
+
+    let mut position = 6;
+    let screen = data.get(position..position + 7).ok_or("GIF ended in its header")?;
+    position += 7 + color_table_size(screen[4]);
+
+    let mut frames = Vec::new();
+    loop {
+        match data.get(position) {
+            // Extensions, such as frame delays, which don't matter here
+            Some(0x21) => {
+                position += 2;
+                read_sub_blocks(data, &mut position)?;
+            },
+            Some(0x2C) => {
+                let descriptor = data.get(position + 1..position + 10).ok_or("GIF ended in an image descriptor")?;
+                let width = u16::from_le_bytes([descriptor[4], descriptor[5]]) as usize;
+                let height = u16::from_le_bytes([descriptor[6], descriptor[7]]) as usize;
+                if width > MAX_FRAME_SIDE || height > MAX_FRAME_SIDE {
+                    return Err(format!("GIF frame is {}x{}, too big for an Octo cartridge", width, height));
+                }
+                if frames.iter().map(Vec::len).sum::<usize>() + width * height > MAX_PIXELS {
+                    return Err("GIF has too many frames for an Octo cartridge".to_string());
+                }
+                position += 10 + color_table_size(descriptor[8]);
+
+                let minimum_code_size = *data.get(position).ok_or("GIF ended before image data")?;
+                position += 1;
+                let compressed = read_sub_blocks(data, &mut position)?;
+                let mut pixels = decompress(&compressed, minimum_code_size)?;
+                pixels.resize(width * height, 0);
+                frames.push(pixels);
+            },
+            Some(0x3B) | None => return Ok(frames),
+            Some(other) => return Err(format!("Unexpected GIF block {:#04X}", other)),
+        }
+    }
+}
+
+// The size of the colour table which follows a block with these flags
+fn color_table_size(flags: u8) -> usize {
+    if flags & 0x80 != 0 {
+        3 << ((flags & 0x07) + 1)
+    } else {
+        0
+    }
+}
+
+// Data split into blocks of up to 255 bytes, each preceded by its length and
+// ending with an empty block
+fn read_sub_blocks(data: &[u8], position: &mut usize) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    loop {
+        let length = *data.get(*position).ok_or("GIF ended in a data block")? as usize;
+        *position += 1;
+        if length == 0 {
+            return Ok(bytes);
+        }
+        bytes.extend_from_slice(data.get(*position..*position + length).ok_or("GIF ended in a data block")?);
+        *position += length;
+    }
+}
+
+// Variable length LZW, with codes packed least significant bit first
+fn decompress(data: &[u8], minimum_code_size: u8) -> Result<Vec<u8>, String> {
+    if !(1..=11).contains(&minimum_code_size) {
+        return Err(format!("Invalid GIF code size {}", minimum_code_size));
+    }
+    let clear_code = 1usize << minimum_code_size;
+    let end_code = clear_code + 1;
+
+    let initial_table = || -> Vec<Vec<u8>> {
+        let mut table: Vec<Vec<u8>> = (0..clear_code).map(|index| vec![index as u8]).collect();
+        table.push(Vec::new());
+        table.push(Vec::new());
+        table
+    };
+    let mut table = initial_table();
+    let mut code_size = minimum_code_size as usize + 1;
+    let mut previous: Option<usize> = None;
+
+    let mut output = Vec::new();
+    let mut bit_buffer: u32 = 0;
+    let mut bit_count = 0;
+    let mut bytes = data.iter();
+    loop {
+        while bit_count < code_size {
+            match bytes.next() {
+                Some(byte) => {
+                    bit_buffer |= (*byte as u32) << bit_count;
+                    bit_count += 8;
+                },
+                None => return Ok(output),
+            }
+        }
+        let code = (bit_buffer & ((1 << code_size) - 1)) as usize;
+        bit_buffer >>= code_size;
+        bit_count -= code_size;
+
+        if code == clear_code {
+            table = initial_table();
+            code_size = minimum_code_size as usize + 1;
+            previous = None;
+            continue;
+        }
+        if code == end_code {
+            return Ok(output);
+        }
+
+        let entry = match previous {
+            None => table.get(code).cloned().ok_or("Invalid GIF data")?,
+            Some(previous) => {
+                let entry = match table.get(code) {
+                    Some(entry) => entry.clone(),
+                    // The code being defined by this very step
+                    None if code == table.len() => {
+                        let mut entry = table[previous].clone();
+                        entry.push(table[previous][0]);
+                        entry
+                    },
+                    None => return Err("Invalid GIF data".to_string()),
+                };
+                if table.len() < 4096 {
+                    let mut new_entry = table[previous].clone();
+                    new_entry.push(entry[0]);
+                    table.push(new_entry);
+                }
+                entry
+            },
+        };
+        output.extend_from_slice(&entry);
+        previous = Some(code);
+
+        if table.len() == 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::control_flow::{self, BasicBlock, Edge, ROM_START};
+use crate::disassembler;
+use crate::platform::Platform;
+
+// The control flow graph of a rom as a Graphviz DOT graph, with a node for
+// each basic block labelled by its disassembly, and the blocks of each
+// subroutine grouped into a cluster
+pub fn control_flow_graph(rom: &[u8], platform: Platform, name: &str) -> String {
+    let analysis = control_flow::analyze(rom, platform);
+    let blocks = analysis.basic_blocks();
+
+    let mut dot = String::new();
+    dot.push_str(&format!("digraph \"{}\" {{\n", escape(name)));
+    dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+    for (entry, members) in subroutines(&blocks) {
+        let name = if entry == ROM_START { "main".to_string() } else { format!("sub_{:03X}", entry) };
+        dot.push_str(&format!("\n    subgraph cluster_{} {{\n", name));
+        dot.push_str(&format!("        label=\"{}\";\n", name));
+        for start in members {
+            let block = &blocks[&start];
+            let mut label = String::new();
+            for address in &block.instructions {
+                let instruction = &analysis.instructions[address];
+                let raw: String = instruction.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                let mnemonic = disassembler::decode(&instruction.bytes, platform).mnemonic.unwrap_or_default();
+                label.push_str(&format!("{:#05X}  {:<8}  {}\\l", address, raw, escape(&mnemonic)));
+            }
+            dot.push_str(&format!("        {} [label=\"{}\"];\n", node(start), label));
+        }
+        dot.push_str("    }\n");
+    }
+
+    // Anything which control passes to, but which isn't code
+    let mut missing = BTreeSet::new();
+    for block in blocks.values() {
+        for (target, _) in &block.successors {
+            if !blocks.contains_key(target) {
+                missing.insert(*target);
+            }
+        }
+    }
+    if !missing.is_empty() || !analysis.indirect_jumps.is_empty() {
+        dot.push('\n');
+    }
+    for target in &missing {
+        dot.push_str(&format!("    {} [label=\"{:#05X}\\nnot code\", shape=note];\n", node(*target), target));
+    }
+    for address in &analysis.indirect_jumps {
+        dot.push_str(&format!("    unknown_{:03X} [label=\"unknown target\", shape=note];\n", address));
+    }
+
+    dot.push('\n');
+    for block in blocks.values() {
+        for (target, edge) in &block.successors {
+            let attributes = match edge {
+                Edge::Next => "",
+                Edge::Jump => " [style=bold]",
+                Edge::NoSkip => " [label=\"no skip\"]",
+                Edge::Skip => " [label=\"skip\", style=dashed]",
+                Edge::Call => " [label=\"call\", style=dotted, color=blue]",
+            };
+            dot.push_str(&format!("    {} -> {}{};\n", node(block.start), node(*target), attributes));
+        }
+
+        let last = block.instructions.last().copied().unwrap_or(block.start);
+        if analysis.indirect_jumps.contains(&last) {
+            dot.push_str(&format!("    {} -> unknown_{:03X} [label=\"jump0\", style=bold];\n", node(block.start), last));
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+fn node(address: u16) -> String {
+    format!("block_{:03X}", address)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// The blocks belonging to main and to each subroutine, found by following
+// everything but calls from their entry points. Code shared between
+// subroutines belongs to the first which reaches it.
+fn subroutines(blocks: &BTreeMap<u16, BasicBlock>) -> Vec<(u16, Vec<u16>)> {
+    let mut entries = vec![ROM_START];
+    for block in blocks.values() {
+        for (target, edge) in &block.successors {
+            if *edge == Edge::Call && blocks.contains_key(target) && !entries.contains(target) {
+                entries.push(*target);
+            }
+        }
+    }
+    entries[1..].sort_unstable();
+
+    let mut assigned = BTreeSet::new();
+    let mut subroutines = Vec::new();
+    for entry in entries {
+        if !blocks.contains_key(&entry) || assigned.contains(&entry) {
+            continue;
+        }
+
+        let mut members = Vec::new();
+        let mut pending = VecDeque::from(vec![entry]);
+        while let Some(start) = pending.pop_front() {
+            if !blocks.contains_key(&start) || !assigned.insert(start) {
+                continue;
+            }
+            members.push(start);
+            for (target, edge) in &blocks[&start].successors {
+                if *edge != Edge::Call {
+                    pending.push_back(*target);
+                }
+            }
+        }
+
+        members.sort_unstable();
+        subroutines.push((entry, members));
+    }
+
+    subroutines
+}
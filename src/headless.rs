@@ -0,0 +1,40 @@
+use crate::palette::Palette;
+use crate::renderer::Renderer;
+
+// A renderer with no window, which only keeps the latest frame, for running
+// without a display
+pub struct Headless {
+    frame_buffer: [[bool; 32]; 64],
+    frames: u64,
+}
+
+impl Headless {
+    pub fn new() -> Self {
+        Self {
+            frame_buffer: [[false; 32]; 64],
+            frames: 0,
+        }
+    }
+
+    pub fn frame_buffer(&self) -> &[[bool; 32]; 64] {
+        &self.frame_buffer
+    }
+
+    // How many frames have been drawn
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+}
+
+impl Renderer for Headless {
+    fn draw_frame(&mut self, frame_buffer: &[[bool; 32]; 64]) {
+        self.frame_buffer = *frame_buffer;
+        self.frames += 1;
+    }
+
+    // There's nothing to show these on
+    fn set_title(&mut self, _title: &str) {}
+
+    fn set_palette(&mut self, _palette: &Palette) {}
+}
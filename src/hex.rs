@@ -0,0 +1,129 @@
+use crate::control_flow::ROM_START;
+use crate::state::MAX_ROM_SIZE;
+
+// Whether a file is text, and so might be a hex listing rather than a rom
+pub fn is_text(data: &[u8]) -> bool {
+    !data.is_empty() && data.iter().all(|byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace())
+}
+
+// A rom written as text: either Intel HEX records, or a hex dump such as
+// "6A 02 6B 0C" or "0x200: 6A02 6B0C", with optional addresses
+pub fn parse(text: &str) -> Result<Vec<u8>, String> {
+    let is_intel_hex = text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .is_some_and(|line| line.starts_with(':'));
+    let segments = if is_intel_hex { parse_intel_hex(text)? } else { parse_dump(text)? };
+    build_rom(segments)
+}
+
+// Records of ":LLAAAATT<data>CC", with a byte count, address, record type
+// and checksum
+fn parse_intel_hex(text: &str) -> Result<Vec<(usize, Vec<u8>)>, String> {
+    let mut segments = Vec::new();
+    // From extended segment and linear address records
+    let mut base = 0;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", line_number + 1, message);
+
+        let record = line.strip_prefix(':')
+            .and_then(decode_hex)
+            .ok_or_else(|| error("Invalid Intel HEX record"))?;
+        if record.len() < 5 || record.len() != 5 + record[0] as usize {
+            return Err(error("Intel HEX record has the wrong length"));
+        }
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(error("Intel HEX record has the wrong checksum"));
+        }
+
+        let address = (record[1] as usize) << 8 | record[2] as usize;
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            0x00 => segments.push((base + address, data.to_vec())),
+            0x01 => break,
+            0x02 if data.len() == 2 => base = ((data[0] as usize) << 8 | data[1] as usize) << 4,
+            0x04 if data.len() == 2 => base = ((data[0] as usize) << 8 | data[1] as usize) << 16,
+            // Start addresses
+            0x03 | 0x05 => {},
+            record_type => return Err(error(&format!("Unsupported Intel HEX record type {:02X}", record_type))),
+        }
+    }
+
+    Ok(segments)
+}
+
+// Whitespace separated hex, in bytes or longer runs of bytes. A token ending
+// in a colon is the address of the bytes which follow it, and # or ; starts a
+// comment.
+fn parse_dump(text: &str) -> Result<Vec<(usize, Vec<u8>)>, String> {
+    let mut segments: Vec<(usize, Vec<u8>)> = vec![(0, Vec::new())];
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.split(['#', ';']).next().unwrap();
+        for token in line.split_whitespace() {
+            let error = || format!("line {}: Invalid hex {}", line_number + 1, token);
+
+            if let Some(address) = token.strip_suffix(':') {
+                let address = strip_hex_prefix(address);
+                let address = usize::from_str_radix(address, 16).map_err(|_| error())?;
+                segments.push((address, Vec::new()));
+                continue;
+            }
+
+            let bytes = decode_hex(strip_hex_prefix(token)).ok_or_else(error)?;
+            segments.last_mut().unwrap().1.extend(bytes);
+        }
+    }
+
+    segments.retain(|(_, bytes)| !bytes.is_empty());
+    if segments.is_empty() {
+        return Err("No hex found".to_string());
+    }
+    Ok(segments)
+}
+
+fn strip_hex_prefix(text: &str) -> &str {
+    text.strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text)
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if text.is_empty() || !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
+
+// Lay the segments out as a rom. Addresses are of CHIP-8 memory if they are
+// all at or after 0x200, and otherwise are offsets into the rom. Either way
+// they must land between 0x200 and the end of memory.
+fn build_rom(segments: Vec<(usize, Vec<u8>)>) -> Result<Vec<u8>, String> {
+    let lowest = segments.iter().map(|(address, _)| *address).min().unwrap_or(0);
+    let base = if lowest >= usize::from(ROM_START) { usize::from(ROM_START) } else { 0 };
+
+    for (address, bytes) in &segments {
+        if (address - base).saturating_add(bytes.len()) > MAX_ROM_SIZE {
+            return Err(format!("Data at {:#X} goes past the end of memory at 0x1000", address));
+        }
+    }
+
+    let mut rom = Vec::new();
+    for (address, bytes) in segments {
+        let offset = address - base;
+        if rom.len() < offset + bytes.len() {
+            rom.resize(offset + bytes.len(), 0);
+        }
+        rom[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    Ok(rom)
+}
@@ -0,0 +1,211 @@
+// DEFLATE decompression, as used by zip archives
+
+const LENGTH_BASES: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+// The order in which the code length code lengths of a dynamic block are sent
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// Decompress data which is known to be at most a certain size, so that a small
+// stream of repeated copies can't expand without end
+pub fn inflate(data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    let mut bits = Bits { data, position: 0 };
+    let mut output = Vec::new();
+
+    loop {
+        let is_last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let length = bits.read(16)?;
+                let complement = bits.read(16)?;
+                if length != !complement & 0xFFFF {
+                    return Err("Invalid stored block length".to_string());
+                }
+                if output.len() + length > max_size {
+                    return Err(too_big(max_size));
+                }
+                for _ in 0..length {
+                    output.push(bits.read(8)? as u8);
+                }
+            },
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(&mut bits, &mut output, &literals, &distances, max_size)?;
+            },
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut output, &literals, &distances, max_size)?;
+            },
+            _ => return Err("Invalid block type".to_string()),
+        }
+
+        if is_last {
+            return Ok(output);
+        }
+    }
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    // In bits
+    position: usize,
+}
+
+impl Bits<'_> {
+    // Bits are packed starting from the least significant
+    fn read(&mut self, count: u8) -> Result<usize, String> {
+        let mut value = 0;
+        for index in 0..count {
+            let byte = *self.data.get(self.position / 8).ok_or("Compressed data ended unexpectedly")?;
+            let bit = (byte >> (self.position % 8)) & 1;
+            value |= (bit as usize) << index;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+// A canonical Huffman code, described by the number of codes of each length
+// and the symbols in order of their codes
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols: Vec<(u8, u16)> = lengths.iter()
+            .enumerate()
+            .filter(|(_, length)| **length != 0)
+            .map(|(symbol, length)| (*length, symbol as u16))
+            .collect();
+        symbols.sort_unstable();
+
+        Self {
+            counts,
+            symbols: symbols.into_iter().map(|(_, symbol)| symbol).collect(),
+        }
+    }
+
+    // Codes are sent most significant bit first, so are read a bit at a time
+    fn decode(&self, bits: &mut Bits) -> Result<u16, String> {
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for count in &self.counts[1..] {
+            code |= bits.read(1)?;
+            let count = *count as usize;
+            if code < first + count {
+                return Ok(self.symbols[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code".to_string())
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), String> {
+    let literal_count = bits.read(5)? + 257;
+    let distance_count = bits.read(5)? + 1;
+    let code_length_count = bits.read(4)? + 4;
+
+    let mut code_length_lengths = [0; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_length_lengths[*index] = bits.read(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths);
+
+    // The literal and distance code lengths, run length encoded together
+    let mut lengths = Vec::new();
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            length @ 0..=15 => (length as u8, 1),
+            16 => (*lengths.last().ok_or("Repeated code length with nothing before it")?, 3 + bits.read(2)?),
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat));
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err("Too many code lengths".to_string());
+    }
+
+    let (literals, distances) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literals), Huffman::new(distances)))
+}
+
+fn too_big(max_size: usize) -> String {
+    format!("Decompressed data is bigger than {} bytes", max_size)
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    max_size: usize,
+) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => {
+                if output.len() == max_size {
+                    return Err(too_big(max_size));
+                }
+                output.push(symbol as u8);
+            },
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                let length = LENGTH_BASES.get(index).ok_or("Invalid length code")? + bits.read(LENGTH_EXTRA_BITS[index])?;
+
+                let index = distances.decode(bits)? as usize;
+                let distance = DISTANCE_BASES.get(index).ok_or("Invalid distance code")? + bits.read(DISTANCE_EXTRA_BITS[index])?;
+                if distance > output.len() {
+                    return Err("Distance is before the start of the data".to_string());
+                }
+                if output.len() + length > max_size {
+                    return Err(too_big(max_size));
+                }
+
+                // One byte at a time, as the copy may overlap itself
+                let start = output.len() - distance;
+                for offset in 0..length {
+                    output.push(output[start + offset]);
+                }
+            },
+        }
+    }
+}
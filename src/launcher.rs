@@ -0,0 +1,196 @@
+extern crate sdl2;
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::EventPump;
+
+use std::fs;
+use std::time::Duration;
+
+use crate::display::Display;
+use crate::renderer::Renderer;
+use crate::loader;
+use crate::palette::Palette;
+use crate::rom_database;
+use crate::state::MAX_ROM_SIZE;
+use crate::zip::Archive;
+
+const HELP: &str = "Up/Down: choose  Enter: play  Esc: quit";
+
+// A menu of the roms in a directory, drawn in the main window, for choosing
+// what to play without restarting
+pub struct Launcher {
+    directory: String,
+    games: Vec<Game>,
+    selected: usize,
+    // The first game shown, when there are too many to fit
+    scroll: usize,
+}
+
+struct Game {
+    path: String,
+    title: String,
+}
+
+impl Launcher {
+    // Every rom in a directory, including those in zip archives, titled from
+    // the rom database where possible
+    pub fn scan(directory: &str) -> Result<Self, String> {
+        let mut paths: Vec<String> = fs::read_dir(directory)
+            .map_err(|e| format!("Failed to read {}: {}", directory, e))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        paths.sort_by_key(|path| path.to_lowercase());
+
+        let mut games = Vec::new();
+        for path in paths {
+            if path.to_lowercase().ends_with(".zip") {
+                // Archives which can't be read are left out rather than
+                // losing the whole menu
+                let data = match fs::read(&path) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        eprintln!("Failed to read {}: {}", path, e);
+                        continue;
+                    },
+                };
+                match Archive::new(&data) {
+                    Ok(archive) => {
+                        for name in archive.names().into_iter().filter(|name| loader::is_rom_file(name)) {
+                            let rom = archive.read(name, MAX_ROM_SIZE).ok();
+                            games.push(Game::new(format!("{}:{}", path, name), rom.as_deref()));
+                        }
+                    },
+                    Err(e) => eprintln!("{}: {}", path, e),
+                }
+            } else if loader::is_rom_file(&path) {
+                let rom = fs::read(&path).ok();
+                games.push(Game::new(path, rom.as_deref()));
+            } else if is_source_file(&path) {
+                games.push(Game::new(path, None));
+            }
+        }
+
+        if games.is_empty() {
+            return Err(format!("No roms found in {}", directory));
+        }
+
+        Ok(Self {
+            directory: directory.to_string(),
+            games,
+            selected: 0,
+            scroll: 0,
+        })
+    }
+
+    // Show the menu until a rom is picked, returning its path, or None if the
+    // user quits
+    pub fn choose(&mut self, display: &mut Display, event_pump: &mut EventPump, palette: &Palette) -> Option<String> {
+        display.set_palette(palette);
+        display.set_title(&format!("rchip8 - {}", self.directory));
+
+        loop {
+            for event in event_pump.poll_iter() {
+                match event {
+                    Event::Quit {..} |
+                    Event::Window { win_event: WindowEvent::Close, .. } |
+                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return None,
+                    Event::KeyDown { keycode: Some(Keycode::Return), .. } => {
+                        return Some(self.games[self.selected].path.clone());
+                    },
+                    Event::KeyDown { keycode: Some(Keycode::F11), .. } => display.toggle_fullscreen(),
+                    Event::KeyDown { keycode: Some(keycode), .. } => {
+                        let page = self.visible_rows(display);
+                        let last = self.games.len() - 1;
+                        self.selected = match keycode {
+                            Keycode::Up => self.selected.saturating_sub(1),
+                            Keycode::Down => (self.selected + 1).min(last),
+                            Keycode::PageUp => self.selected.saturating_sub(page),
+                            Keycode::PageDown => (self.selected + page).min(last),
+                            Keycode::Home => 0,
+                            Keycode::End => last,
+                            _ => self.selected,
+                        };
+                    },
+                    _ => {}
+                }
+            }
+
+            self.draw(display);
+            ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        }
+    }
+
+    // Rows left for games after the heading and help
+    fn visible_rows(&self, display: &Display) -> usize {
+        display.text_size().1.saturating_sub(4).max(1)
+    }
+
+    fn draw(&mut self, display: &mut Display) {
+        let (columns, _) = display.text_size();
+        let rows = self.visible_rows(display);
+
+        // Keep the selection on screen
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        let mut lines = vec![
+            fit(&format!("{} roms in {}", self.games.len(), self.directory), columns),
+            String::new(),
+        ];
+        for game in self.games.iter().skip(self.scroll).take(rows) {
+            lines.push(game.line(columns));
+        }
+        lines.resize(rows + 3, String::new());
+        lines.push(fit(HELP, columns));
+
+        display.draw_text(&lines, Some(2 + self.selected - self.scroll));
+    }
+}
+
+impl Game {
+    // Titled from the rom database if the rom is given
+    fn new(path: String, rom: Option<&[u8]>) -> Self {
+        let entry = rom.and_then(|rom| rom_database::lookup(rom).ok().flatten());
+
+        Self {
+            title: entry.map_or_else(|| file_name(&path).to_string(), |entry| entry.title),
+            path,
+        }
+    }
+
+    // The title, with the file name on the right if there's room
+    fn line(&self, columns: usize) -> String {
+        let file_name = file_name(&self.path);
+        let title = fit(&format!(" {}", self.title), columns);
+        let title_length = title.chars().count();
+        if file_name != self.title && title_length + file_name.chars().count() + 2 < columns {
+            format!("{:<width$}{} ", title, file_name, width = columns - file_name.chars().count() - 1)
+        } else {
+            title
+        }
+    }
+}
+
+// Octo source and cartridges, and hex listings, which are converted when
+// chosen
+fn is_source_file(path: &str) -> bool {
+    let path = path.to_lowercase();
+    path.ends_with(".8o") || path.ends_with(".gif") || path.ends_with(".hex")
+}
+
+// The name of a file, or of a file in an archive
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\', ':']).next().unwrap_or(path)
+}
+
+// Cut text down to a number of characters
+fn fit(text: &str, columns: usize) -> String {
+    text.chars().take(columns).collect()
+}
@@ -0,0 +1,174 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cartridge;
+use crate::hex;
+use crate::octo;
+use crate::rom_database::Entry;
+use crate::state::MAX_ROM_SIZE;
+use crate::zip::Archive;
+
+// The biggest Octo source, cartridge or hex listing read from an archive
+const MAX_SOURCE_SIZE: usize = 1024 * 1024;
+
+// A program ready for State::load_rom, whatever form it was stored in
+pub struct Rom {
+    // Where it was loaded from, including which member of an archive
+    pub path: String,
+    pub data: Vec<u8>,
+    // Labels from source, for use as symbols
+    pub labels: Vec<(u16, String)>,
+    // Settings which came with the program, which take priority over the rom
+    // database
+    pub settings: Option<Entry>,
+}
+
+// Load a raw rom, Octo source (.8o), an Octo cartridge (.gif), or a rom
+// written out as hex, from a file or from inside a zip archive
+pub fn load(path: &str) -> Result<Rom, String> {
+    let (path, data) = read(path)?;
+    let path = path.as_str();
+
+    if path.ends_with(".8o") {
+        let source = String::from_utf8_lossy(&data);
+        let program = octo::compile(&source)
+            .map_err(|e| format!("{}:{}", path, e))?;
+        return Ok(Rom {
+            path: path.to_string(),
+            data: program.rom,
+            labels: program.labels,
+            settings: None,
+        });
+    }
+
+    if cartridge::is_cartridge(&data) {
+        let cartridge = cartridge::decode(&data)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let program = octo::compile(&cartridge.source)
+            .map_err(|e| format!("{} (cartridge source):{}", path, e))?;
+        let title = Path::new(path).file_stem()
+            .map_or(path.into(), |stem| stem.to_string_lossy())
+            .into_owned();
+        return Ok(Rom {
+            path: path.to_string(),
+            data: program.rom,
+            labels: program.labels,
+            settings: Some(Entry {
+                title,
+                authors: Vec::new(),
+                platform: cartridge.platform,
+                quirks: cartridge.quirks,
+                cpu_hz: cartridge.cpu_hz,
+                colors: cartridge.colors,
+                keys: Vec::new(),
+            }),
+        });
+    }
+
+    // A text rom is almost certainly a hex listing, but a rom which happens to
+    // be all printable characters is still run as it is
+    let data = if hex::is_text(&data) {
+        match hex::parse(&String::from_utf8_lossy(&data)) {
+            Ok(rom) => rom,
+            Err(_) if is_rom_file(path) => data,
+            Err(e) => return Err(format!("{}: {}", path, e)),
+        }
+    } else {
+        data
+    };
+
+    Ok(Rom {
+        path: path.to_string(),
+        data,
+        labels: Vec::new(),
+        settings: None,
+    })
+}
+
+pub fn is_rom_file(path: &str) -> bool {
+    let extension = Path::new(path).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+    matches!(extension.as_deref(), Some("ch8") | Some("c8") | Some("sc8") | Some("xo8"))
+}
+
+// Read a file, or a member of a zip archive given as archive.zip:inner/path.ch8.
+// If an archive is given without a member, its rom is used, or the user picks
+// one if there are several. Returns the path of what was read.
+pub fn read(path: &str) -> Result<(String, Vec<u8>), String> {
+    let archive_path = file_path(path);
+    let member = path.get(archive_path.len() + 1..);
+    if member.is_none() && !archive_path.to_ascii_lowercase().ends_with(".zip") {
+        let data = fs::read(path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        return Ok((path.to_string(), data));
+    }
+
+    let archive_data = fs::read(archive_path)
+        .map_err(|e| format!("Failed to read {}: {}", archive_path, e))?;
+    let archive = Archive::new(&archive_data)
+        .map_err(|e| format!("{}: {}", archive_path, e))?;
+
+    let member = match member {
+        Some(member) => member.to_string(),
+        None => {
+            let roms: Vec<&str> = archive.names().into_iter().filter(|name| is_rom_file(name)).collect();
+            choose_member(archive_path, &roms)?
+        },
+    };
+
+    // Anything but a rom is converted to one, so may be bigger
+    let max_size = if is_rom_file(&member) { MAX_ROM_SIZE } else { MAX_SOURCE_SIZE };
+    let data = archive.read(&member, max_size)
+        .map_err(|e| format!("{}: {}", archive_path, e))?;
+    Ok((format!("{}:{}", archive_path, member), data))
+}
+
+// The file on disk which a path refers to, which is the archive for a member
+// of an archive
+pub fn file_path(path: &str) -> &str {
+    match path.to_ascii_lowercase().find(".zip:") {
+        Some(index) => &path[..index + 4],
+        None => path,
+    }
+}
+
+// A file next to a rom with a different extension, such as its cheats. For a
+// member of an archive this is next to the archive, named after both, such as
+// pack.pong.cht for pack.zip:games/pong.ch8.
+pub fn companion_path(path: &str, extension: &str) -> PathBuf {
+    let archive_path = Path::new(file_path(path));
+    match path.get(file_path(path).len() + 1..) {
+        Some(member) => {
+            let stem = Path::new(member).file_stem().unwrap_or_default();
+            let mut name = archive_path.file_stem().unwrap_or_default().to_os_string();
+            name.push(".");
+            name.push(stem);
+            name.push(".");
+            name.push(extension);
+            archive_path.with_file_name(name)
+        },
+        None => archive_path.with_extension(extension),
+    }
+}
+
+fn choose_member(archive_path: &str, roms: &[&str]) -> Result<String, String> {
+    match roms {
+        [] => Err(format!("{} has no .ch8, .c8, .sc8 or .xo8 roms", archive_path)),
+        [rom] => Ok(rom.to_string()),
+        _ => {
+            println!("{} has {} roms:", archive_path, roms.len());
+            for (index, rom) in roms.iter().enumerate() {
+                println!("{:>4}  {}", index + 1, rom);
+            }
+            print!("Which one? ");
+            io::stdout().flush().map_err(|e| e.to_string())?;
+
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line).map_err(|e| e.to_string())?;
+            line.trim().parse::<usize>().ok()
+                .and_then(|choice| roms.get(choice.wrapping_sub(1)))
+                .map(|rom| rom.to_string())
+                .ok_or(format!("{} isn't one of the roms", line.trim()))
+        },
+    }
+}
@@ -1,26 +1,104 @@
+// Without SDL only headless runs are possible, leaving the interactive parts
+// unused
+#![cfg_attr(not(feature = "sdl"), allow(dead_code, unused_imports, unused_macros))]
+
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 
-use sdl2::event::Event;
+#[cfg(feature = "sdl")]
+use sdl2::event::{Event, WindowEvent};
+#[cfg(feature = "sdl")]
 use sdl2::keyboard::Keycode;
+#[cfg(feature = "sdl")]
+use sdl2::{EventPump, Sdl};
 
 use std::env;
 use std::fs;
+use std::path::Path;
+use std::process;
+#[cfg(feature = "sdl")]
+use std::sync::mpsc::Receiver;
 use std::time::Duration;
 use std::time::Instant;
 
+use crate::cheats::{Cheats, Search};
+#[cfg(feature = "sdl")]
 use crate::display::Display;
-use crate::state::State;
-use crate::timing::{TimedSystem, Timing};
+use crate::headless::Headless;
+#[cfg(feature = "sdl")]
+use crate::launcher::Launcher;
+use crate::options::{Command, DisassembleOptions, GraphOptions, InfoOptions, Options, SourceOptions};
+use crate::palette::Palette;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::profiler::Profiler;
+use crate::renderer::Renderer;
+use crate::rom_database::Entry;
+#[cfg(feature = "sdl")]
+use crate::sprite_viewer::SpriteViewer;
+use crate::state::{State, MAX_ROM_SIZE};
+use crate::symbols::Symbols;
+use crate::terminal::{Key, Style, Terminal};
+use crate::timing::{Instruction, TimedSystem, Timing};
+use crate::watcher::Watcher;
 
 mod op_code;
+mod analyzer;
+mod assembler;
+mod cartridge;
+mod cheats;
+mod control_flow;
+mod config;
+mod coverage;
+mod crash_report;
+mod crc32;
+mod disassembler;
+#[cfg(feature = "sdl")]
 mod display;
+#[cfg(feature = "sdl")]
+mod font;
+mod gif;
+mod graphviz;
+mod headless;
+mod hex;
+mod inflate;
+mod json;
+#[cfg(feature = "sdl")]
+mod launcher;
+mod loader;
+mod octo;
+mod options;
+mod palette;
+mod patch;
+mod platform;
+mod profiler;
+mod quirks;
+mod renderer;
+mod rom_database;
+mod sha1;
+#[cfg(feature = "sdl")]
+mod sprite_viewer;
 mod state;
+mod symbols;
+mod terminal;
 mod timing;
+mod watcher;
+mod zip;
 
 const CPU_SYSTEM: &str = "cpu";
 const TIMER_SYSTEM: &str = "timer";
 const DISPLAY_SYSTEM: &str = "display";
 
+// Emulation speeds which can be cycled through with the speed hotkeys
+#[cfg(feature = "sdl")]
+const SPEEDS: [f64; 7] = [0.125, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+#[cfg(feature = "sdl")]
+const NORMAL_SPEED_INDEX: usize = 3;
+
+// How long a key pressed in the terminal is held for, which auto-repeat
+// extends
+const KEY_HOLD: Duration = Duration::from_millis(200);
+
 macro_rules! debug {
     ($( $args:expr ),*) => {
         // println!( $( $args ),* );
@@ -28,43 +106,351 @@ macro_rules! debug {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let rom_file = &args[1];
+    match Command::from_args(env::args().collect()) {
+        #[cfg(feature = "sdl")]
+        Command::Run(options) if !options.headless && !options.terminal => run(options),
+        Command::Run(options) if options.terminal => run_terminal(options),
+        Command::Run(options) => run_headless(options),
+        Command::Disassemble(options) => disassemble(options),
+        Command::Assemble(options) => assemble(options),
+        Command::CompileOcto(options) => compile_octo(options),
+        Command::Info(options) => info(options),
+        Command::Graph(options) => graph(options),
+    }
+}
 
-    let rom_file = fs::read(rom_file)
+fn disassemble(options: DisassembleOptions) {
+    let rom = fs::read(&options.rom_file)
         .expect("Failed to read rom data");
 
-    let sdl_context = sdl2::init().unwrap();
+    if options.recursive {
+        print!("{}", disassembler::recursive_descent(&rom, options.platform));
+    } else if options.source {
+        print!("{}", disassembler::source_listing(&rom, options.platform));
+    } else {
+        print!("{}", disassembler::linear_sweep(&rom, options.platform));
+    }
+}
 
-    let mut display = Display::new(&sdl_context);
-    let mut state = State::new();
-    state.load_rom(rom_file);
+fn assemble(options: SourceOptions) {
+    let source = fs::read_to_string(&options.source_file)
+        .expect("Failed to read source file");
 
-    // Init our timing contoller
-    let mut timing = Timing::new(
-        Instant::now(),
-        vec![
-            TimedSystem::new(CPU_SYSTEM, 700),
-            TimedSystem::new(TIMER_SYSTEM, 60),
-            TimedSystem::new(DISPLAY_SYSTEM, 60),
-        ],
-    );
+    match assembler::assemble(&source) {
+        Ok(rom) => {
+            fs::write(&options.output_file, &rom)
+                .expect("Failed to write rom");
+            println!("Wrote {} bytes to {}", rom.len(), options.output_file);
+        },
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}:{}", options.source_file, error);
+            }
+            process::exit(1);
+        },
+    }
+}
+
+fn compile_octo(options: SourceOptions) {
+    let program = compile_octo_file(&options.source_file);
+    fs::write(&options.output_file, &program.rom)
+        .expect("Failed to write rom");
+    println!("Wrote {} bytes to {}", program.rom.len(), options.output_file);
+}
+
+// Compile an Octo source file, exiting with the error if it fails
+fn compile_octo_file(source_file: &str) -> octo::Program {
+    let source = fs::read_to_string(source_file)
+        .expect("Failed to read source file");
 
+    octo::compile(&source).unwrap_or_else(|error| {
+        eprintln!("{}:{}", source_file, error);
+        process::exit(1);
+    })
+}
+
+fn graph(options: GraphOptions) {
+    let rom = fs::read(&options.rom_file)
+        .expect("Failed to read rom data");
+
+    let name = Path::new(&options.rom_file).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let dot = graphviz::control_flow_graph(&rom, options.platform, &name);
+    match options.output_file {
+        Some(output_file) => fs::write(&output_file, dot).expect("Failed to write graph"),
+        None => print!("{}", dot),
+    }
+}
+
+fn info(options: InfoOptions) {
+    let rom = fs::read(&options.rom_file)
+        .expect("Failed to read rom data");
+
+    print!("{}", analyzer::analyze(&rom).describe());
+}
+
+struct Settings {
+    platform: Platform,
+    quirks: Quirks,
+    cpu_hz: u64,
+    palette: Palette,
+    // The game's inputs named in the rom database, such as up, and the keypad
+    // keys they press
+    game_keys: Vec<(String, usize)>,
+}
+
+// The settings to run with. Anything given on the command line wins, then
+// anything implied by --platform, then the rom database, then what --auto
+// recommends. Colours come from the command line, then the rom, then the
+// config file.
+fn choose_settings(options: &Options, rom: &[u8], embedded: Option<Entry>) -> Settings {
+    let entry = match embedded {
+        Some(entry) => {
+            println!(
+                "Using the settings in {}: {} with {} quirks at {}Hz",
+                entry.title,
+                entry.platform.name(),
+                entry.quirks.name().unwrap_or("custom"),
+                entry.cpu_hz,
+            );
+            Some(entry)
+        },
+        None => {
+            let entry = rom_database::lookup(rom).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                None
+            });
+            if let Some(entry) = &entry {
+                let authors = if entry.authors.is_empty() { "unknown".to_string() } else { entry.authors.join(", ") };
+                println!(
+                    "Found {} by {} in the rom database: {} with {} quirks at {}Hz",
+                    entry.title,
+                    authors,
+                    entry.platform.name(),
+                    entry.quirks.name().unwrap_or("custom"),
+                    entry.cpu_hz,
+                );
+            }
+            entry
+        },
+    };
+
+    let report = if options.auto && entry.is_none() {
+        let report = analyzer::analyze(rom);
+        println!(
+            "Detected {} with {} quirks at {}Hz",
+            report.platform.name(),
+            report.quirks.name().unwrap_or("custom"),
+            report.cpu_hz,
+        );
+        Some(report)
+    } else {
+        None
+    };
+
+    let platform = options.platform
+        .or_else(|| entry.as_ref().map(|entry| entry.platform))
+        .or_else(|| report.as_ref().map(|report| report.platform))
+        .unwrap_or(Platform::Chip8);
+    let quirks = options.quirks
+        .or_else(|| options.platform.map(Quirks::for_platform))
+        .or_else(|| entry.as_ref().map(|entry| entry.quirks))
+        .or_else(|| report.as_ref().map(|report| report.quirks))
+        .unwrap_or_else(|| Quirks::for_platform(platform));
+    let cpu_hz = options.cpu_hz
+        .or_else(|| options.platform.map(quirks::default_cpu_hz))
+        .or_else(|| entry.as_ref().map(|entry| entry.cpu_hz))
+        .or_else(|| report.as_ref().map(|report| report.cpu_hz))
+        .unwrap_or_else(|| quirks::default_cpu_hz(platform));
+
+    let palette = options.palette
+        .or_else(|| entry.as_ref().and_then(|entry| Palette::from_colors(&entry.colors)))
+        .unwrap_or_else(|| user_palette(options));
+    let game_keys = entry.as_ref()
+        .map(|entry| entry.keys.iter().map(|(name, key)| (name.clone(), usize::from(*key & 0xF))).collect())
+        .unwrap_or_default();
+
+    Settings {
+        platform,
+        quirks,
+        cpu_hz,
+        palette,
+        game_keys,
+    }
+}
+
+// How a game ended
+#[cfg(feature = "sdl")]
+#[derive(PartialEq)]
+enum Outcome {
+    // Escape was pressed
+    Stopped,
+    // The window was closed
+    Quit,
+    // The rom couldn't be loaded, or crashed
+    Failed,
+}
+
+#[cfg(feature = "sdl")]
+fn run(options: Options) {
+    let sdl_context = sdl2::init().unwrap();
+    let mut display = Display::new(&sdl_context, &user_palette(&options), options.scale, options.integer_scale);
+    display.set_persistence(options.persistence);
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut cheat_console = None;
+
+    // A directory opens the launcher, which is returned to after each game
+    if Path::new(&options.rom_file).is_dir() {
+        let mut launcher = Launcher::scan(&options.rom_file).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        while let Some(rom_file) = launcher.choose(&mut display, &mut event_pump, &user_palette(&options)) {
+            let options = Options { rom_file, ..options.clone() };
+            if play(&options, &sdl_context, &mut display, &mut event_pump, &mut cheat_console) == Outcome::Quit {
+                break;
+            }
+        }
+    } else if play(&options, &sdl_context, &mut display, &mut event_pump, &mut cheat_console) == Outcome::Failed {
+        process::exit(1);
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn play(
+    options: &Options,
+    sdl_context: &Sdl,
+    display: &mut Display,
+    event_pump: &mut EventPump,
+    cheat_console: &mut Option<Receiver<String>>,
+) -> Outcome {
+    let (program_path, rom, mut symbols, embedded_settings) = match load_program(options, &options.rom_file) {
+        Ok(program) => (program.path, program.rom, program.symbols, program.settings),
+        Err(e) => {
+            eprintln!("{}", e);
+            return Outcome::Failed;
+        },
+    };
+
+    let settings = choose_settings(options, &rom, embedded_settings);
+
+    let (cheat_file, mut cheats) = match load_cheats(options, &program_path) {
+        Ok(cheats) => cheats,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Outcome::Failed;
+        },
+    };
+    let mut cheat_search: Option<Search> = None;
+    // Started once the rom is loaded, which may ask which rom in an archive to
+    // run
+    let cheat_console = cheat_console.get_or_insert_with(cheats::console);
+
+    let mut palette = settings.palette;
+    display.set_palette(&palette);
+    let mut state = start(&settings, rom, &cheats);
+    display.clear_persistence();
+    let mut watcher = options.watch.then(|| Watcher::new(&program_path, Instant::now()));
+    // Execution stops when a watched rom crashes, until it is reloaded
+    let mut halted = false;
+
+    let mut timing = new_timing(&settings, Instant::now());
+
+    let mut speed_index = NORMAL_SPEED_INDEX;
+    let mut step_system: Option<&'static str> = None;
+    display.set_title(&window_title(&options.rom_file, &timing));
+
+    let mut sprite_viewer: Option<SpriteViewer> = None;
+    let mut profiler = options.profile.as_ref().map(|_| Profiler::new());
+
+    let mut outcome = Outcome::Stopped;
+
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit {..} |
+                Event::Quit {..} => {
+                    outcome = Outcome::Quit;
+                    break 'running
+                },
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } => {
+                    match &sprite_viewer {
+                        Some(viewer) if viewer.window_id() == window_id => sprite_viewer = None,
+                        _ => {
+                            outcome = Outcome::Quit;
+                            break 'running
+                        },
+                    }
+                },
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                    // Otherwise nothing is drawn until the next frame, which
+                    // is never while paused
+                    display.redraw();
+                },
+                Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
+                    display.toggle_fullscreen();
+                },
+                Event::KeyDown { keycode: Some(Keycode::F1), .. } => {
+                    sprite_viewer = match sprite_viewer {
+                        Some(_) => None,
+                        None => Some(SpriteViewer::new(sdl_context, 0x200, settings.platform)),
+                    };
+                },
+                Event::KeyDown { keycode: Some(Keycode::F3), .. } => {
+                    print_call_stack(&state, &symbols);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
+                    if timing.is_paused() {
+                        timing.resume(Instant::now());
+                    } else {
+                        timing.pause(Instant::now());
+                    }
+                    display.set_title(&window_title(&options.rom_file, &timing));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F8), .. } => {
+                    palette = palette.next_theme();
+                    display.set_palette(&palette);
+                    println!("Theme: {}", palette.name().unwrap_or("custom"));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F6), .. } => {
+                    step_system = Some(DISPLAY_SYSTEM);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                    step_system = Some(CPU_SYSTEM);
+                },
+                Event::KeyDown { keycode: Some(keycode @ Keycode::Minus), .. } |
+                Event::KeyDown { keycode: Some(keycode @ Keycode::Equals), .. } |
+                Event::KeyDown { keycode: Some(keycode @ Keycode::Num0), .. } => {
+                    speed_index = match keycode {
+                        Keycode::Minus => speed_index.saturating_sub(1),
+                        Keycode::Equals => (speed_index + 1).min(SPEEDS.len() - 1),
+                        _ => NORMAL_SPEED_INDEX,
+                    };
+                    timing.set_speed(Instant::now(), SPEEDS[speed_index]);
+                    display.set_title(&window_title(&options.rom_file, &timing));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F2), .. } => {
+                    sprite_viewer::dump_sprite_regions(state.get_ram(), state.get_coverage());
+                },
+                Event::KeyDown { keycode: Some(keycode), .. } if sprite_viewer.is_some() && is_viewer_key(keycode) => {
+                    let viewer = sprite_viewer.as_mut().unwrap();
+                    match keycode {
+                        Keycode::Tab => viewer.toggle_wide(),
+                        Keycode::Up => viewer.scroll_rows(-1),
+                        Keycode::Down => viewer.scroll_rows(1),
+                        Keycode::PageUp => viewer.scroll_columns(-1),
+                        Keycode::PageDown => viewer.scroll_columns(1),
+                        _ => {}
+                    }
+                },
                 Event::KeyDown { keycode: Some(keycode), .. } => {
-                    if let Some(key_index) = keycode_to_index(keycode) {
+                    if let Some(key_index) = key_index(keycode, &settings.game_keys) {
                         state.key_down(key_index);
                     }
                 },
                 Event::KeyUp { keycode: Some(keycode), .. } => {
-                    if let Some(key_index) = keycode_to_index(keycode) {
+                    if let Some(key_index) = key_index(keycode, &settings.game_keys) {
                         state.key_up(key_index);
                     }
                 },
@@ -72,15 +458,53 @@ fn main() {
             }
         }
 
+        if watcher.as_mut().is_some_and(|watcher| watcher.changed(Instant::now())) {
+            match load_program(options, &program_path) {
+                Ok(reloaded) => {
+                    state = start(&settings, reloaded.rom, &cheats);
+                    display.clear_persistence();
+                    symbols = reloaded.symbols;
+                    halted = false;
+                    if options.watch_reset {
+                        speed_index = NORMAL_SPEED_INDEX;
+                        timing.set_speed(Instant::now(), SPEEDS[speed_index]);
+                        timing.resume(Instant::now());
+                        display.center();
+                        display.set_title(&window_title(&options.rom_file, &timing));
+                    }
+                    println!("Reloaded {}", program_path);
+                },
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        while let Ok(command) = cheat_console.try_recv() {
+            cheats::run_command(&command, state.get_ram(), &mut cheat_search, &mut cheats, &cheat_file);
+        }
+
         // The rest of the game loop goes here...
-        let instructions = timing.get_instructions(Instant::now());
+        let instructions = match step_system.take() {
+            Some(system) => {
+                let instructions = timing.step(Instant::now(), system);
+                display.set_title(&window_title(&options.rom_file, &timing));
+                instructions
+            },
+            None => timing.get_instructions(Instant::now()),
+        };
         for instruction in instructions {
             match instruction.name {
+                CPU_SYSTEM if halted => {},
                 CPU_SYSTEM => {
                     debug!("=== Running cpu for {} cycles", instruction.cycles);
-                    for _ in 0..instruction.cycles {
-                        let op_code = state.next_op();
-                        state.execute_op(op_code);
+                    if let Err(report) = run_cpu(&mut state, instruction.cycles, options, &symbols, &mut profiler, settings.platform) {
+                        eprintln!("{}", report);
+                        if watcher.is_some() {
+                            eprintln!("Waiting for {} to change", program_path);
+                            halted = true;
+                        } else {
+                            outcome = Outcome::Failed;
+                            break 'running;
+                        }
                     }
                 },
                 TIMER_SYSTEM => {
@@ -91,8 +515,12 @@ fn main() {
                 },
                 DISPLAY_SYSTEM => {
                     debug!("=== Running display for {} cycles", instruction.cycles);
+                    cheats.apply(state.get_ram_mut());
                     for _ in 0..instruction.cycles {
-                        display.draw_canvas(state.get_frame_buffer());
+                        display.draw_frame(state.get_frame_buffer());
+                    }
+                    if let Some(viewer) = sprite_viewer.as_mut() {
+                        viewer.draw(state.get_ram(), state.get_coverage());
                     }
                 },
                 unknown => panic!("Unexpected instruction {}", unknown),
@@ -100,8 +528,402 @@ fn main() {
         }
         ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60)); // 60fps
     }
+
+    write_reports(options, profiler, &state, &symbols);
+
+    outcome
+}
+
+// Run without a window, as fast as possible, for --frames frames or until the
+// rom crashes, then print the last frame
+fn run_headless(options: Options) {
+    let mut session = Session::load(&options);
+    let mut renderer = Headless::new();
+    renderer.set_palette(&session.settings.palette);
+
+    // Time is simulated rather than waited for, one frame per step
+    let started = Instant::now();
+    let mut timing = new_timing(&session.settings, started);
+    let mut crash = None;
+    let mut step: u64 = 1;
+    while crash.is_none() && options.frames.is_none_or(|frames| renderer.frames() < frames) {
+        let now = started + Duration::from_nanos(step * 1_000_000_000 / 60);
+        step += 1;
+        crash = session.run(timing.get_instructions(now), &options, &mut renderer).err();
+    }
+
+    print!("{}", frame_text(renderer.frame_buffer()));
+    session.finish(&options, crash);
+}
+
+// Run in the terminal, with the keypad read from its keyboard
+fn run_terminal(options: Options) {
+    let mut session = Session::load(&options);
+    let style = if options.braille { Style::Braille } else { Style::HalfBlocks };
+    let mut terminal = Terminal::new(style).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    terminal.set_palette(&session.settings.palette);
+
+    let mut timing = new_timing(&session.settings, Instant::now());
+    terminal.set_title(&window_title(&options.rom_file, &timing));
+    // When each held key will be let go, as terminals only say when keys are
+    // pressed
+    let mut releases: [Option<Instant>; 16] = [None; 16];
+    let mut watcher = options.watch.then(|| Watcher::new(&session.path, Instant::now()));
+    // Why the rom stopped, which with --watch waits for the file to change
+    let mut crash = None;
+
+    'running: loop {
+        let now = Instant::now();
+        for key in terminal.keys() {
+            match key {
+                Key::Escape => break 'running,
+                Key::Char('\t') => {
+                    session.settings.palette = session.settings.palette.next_theme();
+                    terminal.set_palette(&session.settings.palette);
+                    continue;
+                },
+                _ => {},
+            }
+            if let Some(key_index) = terminal_key_index(key, &session.settings.game_keys) {
+                if releases[key_index].is_none() {
+                    session.state.key_down(key_index);
+                }
+                releases[key_index] = Some(now + KEY_HOLD);
+            }
+        }
+        for (key_index, release) in releases.iter_mut().enumerate() {
+            if release.is_some_and(|release| release <= now) {
+                session.state.key_up(key_index);
+                *release = None;
+            }
+        }
+
+        // Printing would spoil the screen, so problems are shown in the
+        // title until the end
+        if watcher.as_mut().is_some_and(|watcher| watcher.changed(now)) {
+            match session.reload(&options) {
+                Ok(()) => {
+                    crash = None;
+                    terminal.set_title(&window_title(&options.rom_file, &timing));
+                },
+                Err(e) => {
+                    terminal.set_title(&format!("{} failed to load, waiting for it to change", session.path));
+                    crash = Some(e);
+                },
+            }
+        }
+
+        let instructions = timing.get_instructions(now);
+        if crash.is_none() {
+            if let Err(report) = session.run(instructions, &options, &mut terminal) {
+                crash = Some(report);
+                if watcher.is_none() {
+                    break;
+                }
+                terminal.set_title(&format!("{} crashed, waiting for it to change", session.path));
+            }
+        }
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60)); // 60fps
+    }
+
+    // Put the terminal back before printing anything
+    drop(terminal);
+    session.finish(&options, crash);
+}
+
+// A rom running without a window
+struct Session {
+    // Where the rom was loaded from, including which member of an archive
+    path: String,
+    settings: Settings,
+    state: State,
+    symbols: Symbols,
+    cheats: Cheats,
+    profiler: Option<Profiler>,
+}
+
+impl Session {
+    // Load the rom, exiting if it fails
+    fn load(options: &Options) -> Self {
+        let program = load_program(options, &options.rom_file).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        let settings = choose_settings(options, &program.rom, program.settings);
+        let (_, cheats) = load_cheats(options, &program.path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        let state = start(&settings, program.rom, &cheats);
+
+        Self {
+            path: program.path,
+            settings,
+            state,
+            symbols: program.symbols,
+            cheats,
+            profiler: options.profile.as_ref().map(|_| Profiler::new()),
+        }
+    }
+
+    // Load the rom again and restart the machine, keeping the settings
+    fn reload(&mut self, options: &Options) -> Result<(), String> {
+        let program = load_program(options, &self.path)?;
+        self.state = start(&self.settings, program.rom, &self.cheats);
+        self.symbols = program.symbols;
+        Ok(())
+    }
+
+    // Run the systems which are due, returning the crash report if the rom
+    // crashes
+    fn run(&mut self, instructions: Vec<Instruction>, options: &Options, renderer: &mut impl Renderer) -> Result<(), String> {
+        for instruction in instructions {
+            match instruction.name {
+                CPU_SYSTEM => {
+                    run_cpu(&mut self.state, instruction.cycles, options, &self.symbols, &mut self.profiler, self.settings.platform)?;
+                },
+                TIMER_SYSTEM => {
+                    for _ in 0..instruction.cycles {
+                        self.state.decrement_timers();
+                    }
+                },
+                DISPLAY_SYSTEM => {
+                    self.cheats.apply(self.state.get_ram_mut());
+                    for _ in 0..instruction.cycles {
+                        renderer.draw_frame(self.state.get_frame_buffer());
+                    }
+                },
+                unknown => panic!("Unexpected instruction {}", unknown),
+            }
+        }
+        Ok(())
+    }
+
+    // Write the reports asked for, and exit with an error if the rom crashed
+    fn finish(self, options: &Options, crash: Option<String>) {
+        if let Some(report) = &crash {
+            eprintln!("{}", report);
+        }
+        write_reports(options, self.profiler, &self.state, &self.symbols);
+        if crash.is_some() {
+            process::exit(1);
+        }
+    }
+}
+
+// The frame as lines of text, with # for pixels which are on
+fn frame_text(frame_buffer: &[[bool; 32]; 64]) -> String {
+    let mut text = String::new();
+    for y in 0..32 {
+        for column in frame_buffer.iter() {
+            text.push(if column[y] { '#' } else { '.' });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// The cheats file given, or the one next to the rom if there is one
+fn load_cheats(options: &Options, rom_path: &str) -> Result<(String, Cheats), String> {
+    let cheat_file = options.cheats.clone().unwrap_or_else(|| Cheats::default_path(rom_path));
+    let cheats = if Path::new(&cheat_file).is_file() {
+        let cheats = Cheats::load(&cheat_file)?;
+        println!("Loaded {} cheats from {}", cheats.freezes().len(), cheat_file);
+        cheats
+    } else {
+        Cheats::new()
+    };
+    Ok((cheat_file, cheats))
+}
+
+// The colours given on the command line or in the config file, for when the
+// rom has none
+fn user_palette(options: &Options) -> Palette {
+    options.palette.or(options.config.palette).unwrap_or_default()
+}
+
+fn new_timing(settings: &Settings, now: Instant) -> Timing {
+    Timing::new(
+        now,
+        vec![
+            TimedSystem::new(CPU_SYSTEM, settings.cpu_hz),
+            TimedSystem::new(TIMER_SYSTEM, 60),
+            TimedSystem::new(DISPLAY_SYSTEM, 60),
+        ],
+    )
+}
+
+// Run some instructions, returning the crash report if the rom crashes
+fn run_cpu(
+    state: &mut State,
+    cycles: u64,
+    options: &Options,
+    symbols: &Symbols,
+    profiler: &mut Option<Profiler>,
+    platform: Platform,
+) -> Result<(), String> {
+    for _ in 0..cycles {
+        let pc = state.get_pc();
+        let result = state.next_op().and_then(|op_code| {
+            if options.trace {
+                println!("{:<24} {:04X}  {}", symbols.resolve(pc), op_code.word(), op_code.pattern());
+            }
+            if let Some(profiler) = profiler.as_mut() {
+                profiler.record(pc, &op_code);
+            }
+            state.execute_op(op_code)
+        });
+
+        if let Err(error) = result {
+            let report = crash_report::build(state, pc, &error, symbols, platform);
+            if let Some(path) = &options.crash_report {
+                fs::write(path, &report).expect("Failed to write crash report");
+                eprintln!("Wrote crash report to {}", path);
+            }
+            return Err(report);
+        }
+    }
+    Ok(())
+}
+
+fn write_reports(options: &Options, profiler: Option<Profiler>, state: &State, symbols: &Symbols) {
+    if let (Some(profiler), Some(prefix)) = (profiler, &options.profile) {
+        profiler.write_reports(prefix, symbols)
+            .expect("Failed to write profiler reports");
+        println!("Wrote profile to {}.txt and {}.folded", prefix, prefix);
+    }
+
+    if let Some(path) = &options.coverage {
+        state.get_coverage().write(path, state.get_ram(), state.get_rom_size(), symbols)
+            .expect("Failed to write coverage");
+        println!("{}", state.get_coverage().summary(state.get_rom_size()));
+        println!("Wrote coverage to {}", path);
+    }
+}
+
+// A rom, patched if asked to be, with its symbols and any settings it came
+// with
+struct Program {
+    // Which file was loaded, including which member of an archive
+    path: String,
+    rom: Vec<u8>,
+    symbols: Symbols,
+    settings: Option<Entry>,
+}
+
+fn load_program(options: &Options, rom_file: &str) -> Result<Program, String> {
+    // Octo source and cartridges are compiled first, and their labels are the
+    // default symbols
+    let loaded = loader::load(rom_file)?;
+    let mut rom = loaded.data;
+
+    if let Some(patch_file) = &options.patch {
+        let patch_data = fs::read(patch_file)
+            .map_err(|e| format!("{}: {}", patch_file, e))?;
+        rom = patch::apply(&rom, &patch_data)
+            .map_err(|e| format!("{}: {}", patch_file, e))?;
+        println!("Applied {}", patch_file);
+    }
+
+    // Every way of loading a rom ends up here, so this is the one place its
+    // size is checked
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(format!("{}: ROM is {} bytes, at most {} fit", loaded.path, rom.len(), MAX_ROM_SIZE));
+    }
+
+    let path = loaded.path;
+    let symbols = match options.symbols.clone().or_else(|| Symbols::default_path(&path)) {
+        Some(path) => Symbols::load(&path)?,
+        None => Symbols::from_labels(loaded.labels),
+    };
+
+    Ok(Program {
+        path,
+        rom,
+        symbols,
+        settings: loaded.settings,
+    })
+}
+
+// A fresh machine running the rom
+fn start(settings: &Settings, rom: Vec<u8>, cheats: &Cheats) -> State {
+    let mut state = State::new();
+    state.set_quirks(settings.quirks);
+    state.load_rom(rom);
+    cheats.apply(state.get_ram_mut());
+    state
+}
+
+#[cfg(feature = "sdl")]
+fn print_call_stack(state: &State, symbols: &Symbols) {
+    println!("PC: {}", symbols.resolve(state.get_pc()));
+    println!("Call stack:");
+    for (depth, return_address) in state.get_stack().iter().rev().enumerate() {
+        // The call itself is the instruction before the return address
+        println!("    #{} called from {}", depth, symbols.resolve(return_address.wrapping_sub(2)));
+    }
+}
+
+fn window_title(rom_file: &str, timing: &Timing) -> String {
+    let rom_name = Path::new(rom_file).file_name()
+        .map_or(rom_file.into(), |name| name.to_string_lossy());
+    let paused = if timing.is_paused() { " [paused]" } else { "" };
+    format!("rchip8 - {} - {}x{}", rom_name, timing.get_speed(), paused)
+}
+
+#[cfg(feature = "sdl")]
+fn is_viewer_key(keycode: Keycode) -> bool {
+    matches!(keycode, Keycode::Tab | Keycode::Up | Keycode::Down | Keycode::PageUp | Keycode::PageDown)
+}
+
+// The game's own keys take priority over the keypad
+#[cfg(feature = "sdl")]
+fn key_index(keycode: Keycode, game_keys: &[(String, usize)]) -> Option<usize> {
+    game_keys.iter()
+        .find(|(name, _)| input_keycode(name) == Some(keycode))
+        .map(|(_, key_index)| *key_index)
+        .or_else(|| keycode_to_index(keycode))
+}
+
+// The terminal's keys for the inputs named in the rom database, and the keypad,
+// laid out as in the window
+fn terminal_key_index(key: Key, game_keys: &[(String, usize)]) -> Option<usize> {
+    let game_key = |name: &str| match name {
+        "up" => Some(Key::Up),
+        "down" => Some(Key::Down),
+        "left" => Some(Key::Left),
+        "right" => Some(Key::Right),
+        "a" => Some(Key::Char(' ')),
+        "b" => Some(Key::Char('\r')),
+        _ => None,
+    };
+    game_keys.iter()
+        .find(|(name, _)| game_key(name) == Some(key))
+        .map(|(_, key_index)| *key_index)
+        .or(match key {
+            Key::Char(c) => "1234qwerasdfzxcv".find(c),
+            _ => None,
+        })
+}
+
+// Keys for the inputs named in the rom database
+#[cfg(feature = "sdl")]
+fn input_keycode(name: &str) -> Option<Keycode> {
+    match name {
+        "up" => Some(Keycode::Up),
+        "down" => Some(Keycode::Down),
+        "left" => Some(Keycode::Left),
+        "right" => Some(Keycode::Right),
+        "a" => Some(Keycode::Space),
+        "b" => Some(Keycode::Return),
+        _ => None,
+    }
 }
 
+#[cfg(feature = "sdl")]
 fn keycode_to_index(keycode: Keycode) -> Option<usize> {
     return match keycode {
         Keycode::Num1 => Some(0),
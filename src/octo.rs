@@ -0,0 +1,883 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::f64::consts;
+use std::fmt;
+
+const ROM_START: u16 = 0x200;
+const MEMORY_SIZE: u16 = 0x1000;
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+pub struct Program {
+    pub rom: Vec<u8>,
+    // Every label, sorted by address, for use as symbols
+    pub labels: Vec<(u16, String)>,
+}
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    arguments: Vec<String>,
+    body: Vec<Token>,
+}
+
+// How to fill in an address which wasn't known when it was used
+#[derive(Clone, Copy)]
+enum FixupKind {
+    // The low 12 bits of an instruction
+    Address,
+    // Both bytes of XO-CHIP's F000 NNNN
+    Long,
+    // The high bits of the address, or'd into a byte
+    HighByte,
+    LowByte,
+}
+
+struct Fixup {
+    offset: usize,
+    kind: FixupKind,
+    name: String,
+    line: usize,
+}
+
+// An open if ... begin, loop or while, holding the address of the jump
+// which needs filling in when the block ends
+enum Block {
+    Begin(u16),
+    Else(u16),
+    Loop(u16),
+    While(u16),
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Value(u16),
+}
+
+enum Condition {
+    Key { x: u8, pressed: bool },
+    Equal { x: u8, equal: bool, operand: Operand },
+    Compare { x: u8, comparison: &'static str, operand: Operand },
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    line: usize,
+    rom: Vec<u8>,
+    here: u16,
+    jump_to_main: bool,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, usize)>,
+}
+
+// Compile Octo source into a rom which is loaded at 0x200. Execution starts
+// at the main label, via a jump unless main is the first thing in the rom.
+pub fn compile(source: &str) -> Result<Program, CompileError> {
+    let mut compiler = Compiler {
+        tokens: tokenize(source),
+        line: 1,
+        rom: Vec::new(),
+        here: ROM_START,
+        jump_to_main: true,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+
+    // Room for the jump to main
+    compiler.word(0x0000)?;
+
+    while let Some(token) = compiler.next_token() {
+        compiler.statement(token)?;
+    }
+
+    compiler.finish()
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        for text in line.split_whitespace() {
+            if text.starts_with('#') {
+                break;
+            }
+            tokens.push_back(Token {
+                text: text.to_string(),
+                line: index + 1,
+            });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value as f64 } else { value as f64 })
+}
+
+fn is_binary_operator(text: &str) -> bool {
+    [
+        "-", "+", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", "==", "!=", ">=", ">",
+    ].contains(&text)
+}
+
+fn apply_binary(operator: &str, left: f64, right: f64) -> f64 {
+    let bits = |f: fn(i64, i64) -> i64| f(left as i64, right as i64) as f64;
+    let truth = |value: bool| if value { 1.0 } else { 0.0 };
+    match operator {
+        "-" => left - right,
+        "+" => left + right,
+        "*" => left * right,
+        "/" => left / right,
+        "%" => left % right,
+        "&" => bits(|a, b| a & b),
+        "|" => bits(|a, b| a | b),
+        "^" => bits(|a, b| a ^ b),
+        "<<" => bits(|a, b| a << b),
+        ">>" => bits(|a, b| a >> b),
+        "pow" => left.powf(right),
+        "min" => left.min(right),
+        "max" => left.max(right),
+        "<" => truth(left < right),
+        "<=" => truth(left <= right),
+        "==" => truth(left == right),
+        "!=" => truth(left != right),
+        ">=" => truth(left >= right),
+        ">" => truth(left > right),
+        _ => unreachable!(),
+    }
+}
+
+impl Compiler {
+    fn error<T>(&self, message: String) -> Result<T, CompileError> {
+        Err(CompileError {
+            line: self.line,
+            message,
+        })
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.pop_front()?;
+        self.line = token.line;
+        Some(token)
+    }
+
+    fn expect_token(&mut self, expected: &str) -> Result<Token, CompileError> {
+        match self.next_token() {
+            Some(token) => Ok(token),
+            None => self.error(format!("Expected {} at the end of the file", expected)),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), CompileError> {
+        let token = self.expect_token(expected)?;
+        if token.text != expected {
+            return self.error(format!("Expected {}, not {}", expected, token.text));
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, CompileError> {
+        let token = self.expect_token("a name")?;
+        if parse_number(&token.text).is_some() || self.register_of(&token.text).is_some() {
+            return self.error(format!("{} can't be used as a name", token.text));
+        }
+        Ok(token.text)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), CompileError> {
+        if usize::from(self.here) + bytes.len() > usize::from(MEMORY_SIZE) {
+            return self.error("The program doesn't fit in memory".to_string());
+        }
+
+        let offset = usize::from(self.here - ROM_START);
+        if self.rom.len() < offset + bytes.len() {
+            self.rom.resize(offset + bytes.len(), 0);
+        }
+        self.rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.here += bytes.len() as u16;
+        Ok(())
+    }
+
+    fn word(&mut self, word: u16) -> Result<(), CompileError> {
+        self.emit(&[(word >> 8) as u8, word as u8])
+    }
+
+    fn offset(&self) -> usize {
+        usize::from(self.here - ROM_START)
+    }
+
+    fn register_of(&self, text: &str) -> Option<u8> {
+        if let Some(register) = self.aliases.get(text) {
+            return Some(*register);
+        }
+        match text.as_bytes() {
+            [b'v', digit] | [b'V', digit] if digit.is_ascii_hexdigit() => {
+                u8::from_str_radix(&text[1..], 16).ok()
+            },
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, CompileError> {
+        let token = self.expect_token("a register")?;
+        match self.register_of(&token.text) {
+            Some(register) => Ok(register),
+            None => self.error(format!("Expected a register, not {}", token.text)),
+        }
+    }
+
+    // Numbers, constants and labels which are already defined
+    fn known_value(&self, text: &str) -> Option<f64> {
+        parse_number(text)
+            .or_else(|| self.constants.get(text).copied())
+            .or_else(|| self.labels.get(text).map(|address| f64::from(*address)))
+    }
+
+    fn value_f64(&mut self, token: Token) -> Result<f64, CompileError> {
+        if token.text == "{" {
+            return self.calc();
+        }
+        match self.known_value(&token.text) {
+            Some(value) => Ok(value),
+            None => self.error(format!("Undefined name {}", token.text)),
+        }
+    }
+
+    // A value which fits in the given number of bits, where negative values
+    // are allowed as two's complement
+    fn value_of(&mut self, token: Token, bits: u32) -> Result<u16, CompileError> {
+        let text = token.text.clone();
+        let value = self.value_f64(token)?.floor() as i64;
+        let limit = 1i64 << bits;
+        if value < -(limit / 2) || value >= limit {
+            let description = if parse_number(&text).is_some() { text } else { format!("{} ({})", text, value) };
+            return self.error(format!("{} doesn't fit in {} bits", description, bits));
+        }
+        Ok((value & (limit - 1)) as u16)
+    }
+
+    fn value(&mut self, bits: u32) -> Result<u16, CompileError> {
+        let token = self.expect_token("a value")?;
+        self.value_of(token, bits)
+    }
+
+    fn operand(&mut self) -> Result<Operand, CompileError> {
+        let token = self.expect_token("a register or value")?;
+        match self.register_of(&token.text) {
+            Some(register) => Ok(Operand::Register(register)),
+            None => Ok(Operand::Value(self.value_of(token, 8)?)),
+        }
+    }
+
+    // An address, which may be a label that hasn't been defined yet, in
+    // which case it's filled in later and zero is returned for now
+    fn address_of(&mut self, token: Token, fixups: &[(usize, FixupKind)]) -> Result<u16, CompileError> {
+        if token.text == "{" || self.known_value(&token.text).is_some() {
+            return self.value_of(token, 16);
+        }
+
+        for (offset, kind) in fixups {
+            self.fixups.push(Fixup {
+                offset: *offset,
+                kind: *kind,
+                name: token.text.clone(),
+                line: token.line,
+            });
+        }
+        Ok(0)
+    }
+
+    // An instruction with a 12 bit address, such as jump or call
+    fn address_instruction(&mut self, op: u16, token: Token) -> Result<(), CompileError> {
+        let offset = self.offset();
+        let address = self.address_of(token, &[(offset, FixupKind::Address)])?;
+        if address >= MEMORY_SIZE {
+            return self.error(format!("{:#X} is not a 12 bit address", address));
+        }
+        self.word(op | address)
+    }
+
+    fn define_label(&mut self, name: String, address: u16) -> Result<(), CompileError> {
+        if self.labels.contains_key(&name) {
+            return self.error(format!("{} is already defined", name));
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), CompileError> {
+        if let Some(x) = self.register_of(&token.text) {
+            return self.register_statement(x);
+        }
+
+        if let Some(definition) = self.macros.get(&token.text) {
+            let mut arguments = HashMap::new();
+            for name in definition.arguments.clone() {
+                let argument = self.expect_token("a macro argument")?;
+                arguments.insert(name, argument.text);
+            }
+
+            let definition = &self.macros[&token.text];
+            for body_token in definition.body.iter().rev() {
+                let text = arguments.get(&body_token.text).unwrap_or(&body_token.text).clone();
+                self.tokens.push_front(Token {
+                    text,
+                    line: body_token.line,
+                });
+            }
+            return Ok(());
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                // No jump is needed if main is the first thing in the rom
+                if name == "main" && self.jump_to_main && self.here == ROM_START + 2 {
+                    self.rom.clear();
+                    self.here = ROM_START;
+                    self.jump_to_main = false;
+                }
+                self.define_label(name, self.here)?;
+            },
+            ":next" => {
+                // Labels the second byte of the next instruction, for self
+                // modifying code
+                let name = self.name()?;
+                self.define_label(name, self.here + 1)?;
+            },
+            ":const" => {
+                let name = self.name()?;
+                let token = self.expect_token("a value")?;
+                let value = self.value_f64(token)?;
+                self.constants.insert(name, value);
+            },
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            },
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            },
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let byte = self.value(8)?;
+                self.emit(&[byte as u8])?;
+            },
+            ":org" => {
+                let address = self.value(12)?;
+                if address < ROM_START {
+                    return self.error(format!("Can't org to {:#05X}, before the start of the rom", address));
+                }
+                self.here = address;
+            },
+            ":unpack" => {
+                // v0 and v1 := an address, with a nibble above it unless long
+                let token = self.expect_token("long or a nibble")?;
+                let high = if token.text == "long" { 0 } else { self.value_of(token, 4)? << 4 };
+                let offset = self.offset();
+                let token = self.expect_token("an address")?;
+                let address = self.address_of(token, &[(offset + 1, FixupKind::HighByte), (offset + 3, FixupKind::LowByte)])?;
+                self.word(0x6000 | high | (address >> 8))?;
+                self.word(0x6100 | (address & 0xFF))?;
+            },
+            ":call" => {
+                let token = self.expect_token("an address")?;
+                self.address_instruction(0x2000, token)?;
+            },
+            ":breakpoint" | ":proto" => {
+                self.expect_token("a name")?;
+            },
+            ":monitor" => {
+                self.expect_token("an address")?;
+                self.expect_token("a format")?;
+            },
+
+            "return" | ";" => self.word(0x00EE)?,
+            "clear" => self.word(0x00E0)?,
+            "bcd" => {
+                let x = self.register()?;
+                self.word(0xF033 | (u16::from(x) << 8))?;
+            },
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.tokens.front().map(|token| token.text.as_str()) == Some("-") {
+                    self.next_token();
+                    let y = self.register()?;
+                    let op = if token.text == "save" { 0x5002 } else { 0x5003 };
+                    self.word(op | (u16::from(x) << 8) | (u16::from(y) << 4))?;
+                } else {
+                    let op = if token.text == "save" { 0xF055 } else { 0xF065 };
+                    self.word(op | (u16::from(x) << 8))?;
+                }
+            },
+            "saveflags" => {
+                let x = self.register()?;
+                self.word(0xF075 | (u16::from(x) << 8))?;
+            },
+            "loadflags" => {
+                let x = self.register()?;
+                self.word(0xF085 | (u16::from(x) << 8))?;
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.value(4)?;
+                self.word(0xD000 | (u16::from(x) << 8) | (u16::from(y) << 4) | n)?;
+            },
+            "jump" | "jump0" | "native" => {
+                let op = match token.text.as_str() {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                };
+                let token = self.expect_token("an address")?;
+                self.address_instruction(op, token)?;
+            },
+
+            // SCHIP
+            "hires" => self.word(0x00FF)?,
+            "lores" => self.word(0x00FE)?,
+            "scroll-down" => {
+                let n = self.value(4)?;
+                self.word(0x00C0 | n)?;
+            },
+            "scroll-left" => self.word(0x00FC)?,
+            "scroll-right" => self.word(0x00FB)?,
+            "exit" => self.word(0x00FD)?,
+
+            // XO-CHIP
+            "scroll-up" => {
+                let n = self.value(4)?;
+                self.word(0x00D0 | n)?;
+            },
+            "plane" => {
+                let n = self.value(4)?;
+                self.word(0xF001 | (n << 8))?;
+            },
+            "audio" => self.word(0xF002)?,
+
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let op = match token.text.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.word(op | (u16::from(x) << 8))?;
+            },
+            "i" => self.index_statement()?,
+
+            "if" => {
+                let condition = self.condition()?;
+                let keyword = self.expect_token("then or begin")?;
+                match keyword.text.as_str() {
+                    "then" => self.skip_unless(&condition, false)?,
+                    "begin" => {
+                        self.skip_unless(&condition, true)?;
+                        self.blocks.push((Block::Begin(self.here), self.line));
+                        self.word(0x1000)?;
+                    },
+                    _ => return self.error(format!("Expected then or begin, not {}", keyword.text)),
+                }
+            },
+            "else" => match self.blocks.pop() {
+                Some((Block::Begin(jump), _)) => {
+                    self.blocks.push((Block::Else(self.here), self.line));
+                    self.word(0x1000)?;
+                    self.patch_jump(jump, self.here);
+                },
+                _ => return self.error("else without if ... begin".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::Begin(jump), _)) | Some((Block::Else(jump), _)) => self.patch_jump(jump, self.here),
+                _ => return self.error("end without if ... begin".to_string()),
+            },
+            "loop" => self.blocks.push((Block::Loop(self.here), self.line)),
+            "while" => {
+                let condition = self.condition()?;
+                self.skip_unless(&condition, true)?;
+                self.blocks.push((Block::While(self.here), self.line));
+                self.word(0x1000)?;
+            },
+            "again" => {
+                let mut exits = Vec::new();
+                loop {
+                    match self.blocks.pop() {
+                        Some((Block::While(jump), _)) => exits.push(jump),
+                        Some((Block::Loop(start), _)) => {
+                            self.word(0x1000 | start)?;
+                            break;
+                        },
+                        _ => return self.error("again without loop".to_string()),
+                    }
+                }
+                for jump in exits {
+                    self.patch_jump(jump, self.here);
+                }
+            },
+
+            // Numbers and constants are data, and anything else is a call
+            text if text == "{" || (self.known_value(text).is_some() && !self.labels.contains_key(text)) => {
+                let byte = self.value_of(token, 8)?;
+                self.emit(&[byte as u8])?;
+            },
+            _ => self.address_instruction(0x2000, token)?,
+        }
+
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), CompileError> {
+        let x_bits = u16::from(x) << 8;
+        let operator = self.expect_token("an operator")?;
+        let xy = |op: u16, y: u8| op | x_bits | (u16::from(y) << 4);
+
+        let word = match operator.text.as_str() {
+            ":=" => {
+                let token = self.expect_token("a value")?;
+                match token.text.as_str() {
+                    "random" => 0xC000 | x_bits | self.value(8)?,
+                    "key" => 0xF00A | x_bits,
+                    "delay" => 0xF007 | x_bits,
+                    _ => match self.register_of(&token.text) {
+                        Some(y) => xy(0x8000, y),
+                        None => 0x6000 | x_bits | self.value_of(token, 8)?,
+                    },
+                }
+            },
+            "+=" => match self.operand()? {
+                Operand::Register(y) => xy(0x8004, y),
+                Operand::Value(value) => 0x7000 | x_bits | value,
+            },
+            "-=" => match self.operand()? {
+                Operand::Register(y) => xy(0x8005, y),
+                Operand::Value(value) => 0x7000 | x_bits | (value.wrapping_neg() & 0xFF),
+            },
+            "|=" => xy(0x8001, self.register()?),
+            "&=" => xy(0x8002, self.register()?),
+            "^=" => xy(0x8003, self.register()?),
+            ">>=" => xy(0x8006, self.register()?),
+            "=-" => xy(0x8007, self.register()?),
+            "<<=" => xy(0x800E, self.register()?),
+            _ => return self.error(format!("Unknown operator {}", operator.text)),
+        };
+
+        self.word(word)
+    }
+
+    fn index_statement(&mut self) -> Result<(), CompileError> {
+        let operator = self.expect_token("an operator")?;
+        match operator.text.as_str() {
+            ":=" => {
+                let token = self.expect_token("an address")?;
+                match token.text.as_str() {
+                    "hex" => {
+                        let x = self.register()?;
+                        self.word(0xF029 | (u16::from(x) << 8))
+                    },
+                    "bighex" => {
+                        let x = self.register()?;
+                        self.word(0xF030 | (u16::from(x) << 8))
+                    },
+                    "long" => {
+                        let offset = self.offset();
+                        let token = self.expect_token("an address")?;
+                        let address = self.address_of(token, &[(offset + 2, FixupKind::Long)])?;
+                        self.word(0xF000)?;
+                        self.word(address)
+                    },
+                    _ => self.address_instruction(0xA000, token),
+                }
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.word(0xF01E | (u16::from(x) << 8))
+            },
+            _ => self.error(format!("Unknown operator i {}", operator.text)),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, CompileError> {
+        let x = self.register()?;
+        let operator = self.expect_token("a comparison")?;
+        let condition = match operator.text.as_str() {
+            "key" => Condition::Key { x, pressed: true },
+            "-key" => Condition::Key { x, pressed: false },
+            "==" => Condition::Equal { x, equal: true, operand: self.operand()? },
+            "!=" => Condition::Equal { x, equal: false, operand: self.operand()? },
+            "<" | ">" | "<=" | ">=" => {
+                if x == 0xF {
+                    return self.error(format!("vf can't be compared with {}, as it is used for the result", operator.text));
+                }
+                let comparison = match operator.text.as_str() {
+                    "<" => "<",
+                    ">" => ">",
+                    "<=" => "<=",
+                    _ => ">=",
+                };
+                Condition::Compare { x, comparison, operand: self.operand()? }
+            },
+            _ => return self.error(format!("Unknown comparison {}", operator.text)),
+        };
+        Ok(condition)
+    }
+
+    // Skip the next instruction unless the condition holds, or if inverted,
+    // skip it if the condition holds
+    fn skip_unless(&mut self, condition: &Condition, inverted: bool) -> Result<(), CompileError> {
+        match *condition {
+            Condition::Key { x, pressed } => {
+                let op = if pressed != inverted { 0xE0A1 } else { 0xE09E };
+                self.word(op | (u16::from(x) << 8))
+            },
+            Condition::Equal { x, equal, operand } => {
+                let skip_if_equal = equal == inverted;
+                let x_bits = u16::from(x) << 8;
+                match operand {
+                    Operand::Register(y) => {
+                        let op = if skip_if_equal { 0x5000 } else { 0x9000 };
+                        self.word(op | x_bits | (u16::from(y) << 4))
+                    },
+                    Operand::Value(value) => {
+                        let op = if skip_if_equal { 0x3000 } else { 0x4000 };
+                        self.word(op | x_bits | value)
+                    },
+                }
+            },
+            Condition::Compare { x, comparison, operand } => {
+                // Subtract in vf, whose flag is then whether nothing was
+                // borrowed
+                match operand {
+                    Operand::Register(y) => self.word(0x8F00 | (u16::from(y) << 4))?,
+                    Operand::Value(value) => self.word(0x6F00 | value)?,
+                }
+                let (subtract, skip_if_set) = match comparison {
+                    // vf := x - operand, so vf is set when x >= operand
+                    "<" => (0x8F07, true),
+                    ">=" => (0x8F07, false),
+                    // vf := operand - x, so vf is set when x <= operand
+                    ">" => (0x8F05, true),
+                    _ => (0x8F05, false),
+                };
+                self.word(subtract | (u16::from(x) << 4))?;
+                let op = if skip_if_set != inverted { 0x4F00 } else { 0x3F00 };
+                self.word(op)
+            },
+        }
+    }
+
+    fn patch_jump(&mut self, jump: u16, target: u16) {
+        let offset = usize::from(jump - ROM_START);
+        self.rom[offset] = 0x10 | (target >> 8) as u8;
+        self.rom[offset + 1] = target as u8;
+    }
+
+    fn define_macro(&mut self) -> Result<(), CompileError> {
+        let name = self.name()?;
+        let mut arguments = Vec::new();
+        loop {
+            let token = self.expect_token("{")?;
+            if token.text == "{" {
+                break;
+            }
+            arguments.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.expect_token("}")?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                },
+                _ => {},
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro {
+            arguments,
+            body,
+        });
+        Ok(())
+    }
+
+    // The rest of a { ... } expression. Operators have no precedence, and
+    // are evaluated right to left.
+    fn calc(&mut self) -> Result<f64, CompileError> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.expect_token("}")?;
+            if token.text == "}" {
+                break;
+            }
+            tokens.push(token.text);
+        }
+
+        let mut position = 0;
+        let value = self.expression(&tokens, &mut position)?;
+        if position < tokens.len() {
+            return self.error(format!("Unexpected {} in expression", tokens[position]));
+        }
+        Ok(value)
+    }
+
+    fn expression(&self, tokens: &[String], position: &mut usize) -> Result<f64, CompileError> {
+        let left = self.term(tokens, position)?;
+        match tokens.get(*position) {
+            Some(operator) if is_binary_operator(operator) => {
+                *position += 1;
+                let right = self.expression(tokens, position)?;
+                Ok(apply_binary(operator, left, right))
+            },
+            _ => Ok(left),
+        }
+    }
+
+    fn term(&self, tokens: &[String], position: &mut usize) -> Result<f64, CompileError> {
+        let token = match tokens.get(*position) {
+            Some(token) => token,
+            None => return self.error("Incomplete expression".to_string()),
+        };
+        *position += 1;
+
+        let value = match token.as_str() {
+            "(" => {
+                let value = self.expression(tokens, position)?;
+                if tokens.get(*position).map(String::as_str) != Some(")") {
+                    return self.error("Expected ) in expression".to_string());
+                }
+                *position += 1;
+                value
+            },
+            "-" => -self.term(tokens, position)?,
+            "~" => !(self.term(tokens, position)? as i64) as f64,
+            "!" => if self.term(tokens, position)? == 0.0 { 1.0 } else { 0.0 },
+            "sin" => self.term(tokens, position)?.sin(),
+            "cos" => self.term(tokens, position)?.cos(),
+            "tan" => self.term(tokens, position)?.tan(),
+            "exp" => self.term(tokens, position)?.exp(),
+            "log" => self.term(tokens, position)?.ln(),
+            "abs" => self.term(tokens, position)?.abs(),
+            "sqrt" => self.term(tokens, position)?.sqrt(),
+            "sign" => self.term(tokens, position)?.signum(),
+            "ceil" => self.term(tokens, position)?.ceil(),
+            "floor" => self.term(tokens, position)?.floor(),
+            "@" => {
+                // The byte already compiled at an address
+                let address = self.term(tokens, position)? as i64;
+                let offset = address - i64::from(ROM_START);
+                let byte = usize::try_from(offset).ok().and_then(|offset| self.rom.get(offset));
+                f64::from(byte.copied().unwrap_or(0))
+            },
+            "HERE" => f64::from(self.here),
+            "PI" => consts::PI,
+            "E" => consts::E,
+            text => match self.known_value(text) {
+                Some(value) => value,
+                None => return self.error(format!("Undefined name {} in expression", text)),
+            },
+        };
+        Ok(value)
+    }
+
+    fn finish(mut self) -> Result<Program, CompileError> {
+        if let Some((_, line)) = self.blocks.last() {
+            self.line = *line;
+            return self.error("This block is never closed".to_string());
+        }
+
+        for fixup in &self.fixups {
+            let address = match self.labels.get(&fixup.name) {
+                Some(address) => *address,
+                None => {
+                    return Err(CompileError {
+                        line: fixup.line,
+                        message: format!("Undefined name {}", fixup.name),
+                    });
+                },
+            };
+
+            let offset = fixup.offset;
+            match fixup.kind {
+                FixupKind::Address => {
+                    self.rom[offset] |= (address >> 8) as u8;
+                    self.rom[offset + 1] = address as u8;
+                },
+                FixupKind::Long => {
+                    self.rom[offset] = (address >> 8) as u8;
+                    self.rom[offset + 1] = address as u8;
+                },
+                FixupKind::HighByte => self.rom[offset] |= (address >> 8) as u8,
+                FixupKind::LowByte => self.rom[offset] = address as u8,
+            }
+        }
+
+        if self.jump_to_main {
+            match self.labels.get("main") {
+                Some(main) => {
+                    self.rom[0] = 0x10 | (main >> 8) as u8;
+                    self.rom[1] = *main as u8;
+                },
+                None => return self.error("There is no main label".to_string()),
+            }
+        }
+
+        let mut labels: Vec<(u16, String)> = self.labels.into_iter()
+            .map(|(name, address)| (address, name))
+            .collect();
+        labels.sort();
+
+        Ok(Program {
+            rom: self.rom,
+            labels,
+        })
+    }
+}
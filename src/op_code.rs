@@ -19,4 +19,52 @@ impl OpCode {
             nnn: (u16::from(byte1 & 0xF) << 8) | u16::from(byte2),
         }
     }
+
+    // The raw 16 bit instruction
+    pub fn word(&self) -> u16 {
+        (u16::from(self.op) << 12) | (u16::from(self.x) << 8) | u16::from(self.nn)
+    }
+
+    // The conventional name of the instruction, e.g. "8XY4", used to group
+    // op codes by class
+    pub fn pattern(&self) -> &'static str {
+        match (self.op, self.x, self.y, self.n) {
+            (0x0, 0x0, 0xE, 0x0) => "00E0",
+            (0x0, 0x0, 0xE, 0xE) => "00EE",
+            (0x0, _, _, _) => "0NNN",
+            (0x1, _, _, _) => "1NNN",
+            (0x2, _, _, _) => "2NNN",
+            (0x3, _, _, _) => "3XNN",
+            (0x4, _, _, _) => "4XNN",
+            (0x5, _, _, 0x0) => "5XY0",
+            (0x6, _, _, _) => "6XNN",
+            (0x7, _, _, _) => "7XNN",
+            (0x8, _, _, 0x0) => "8XY0",
+            (0x8, _, _, 0x1) => "8XY1",
+            (0x8, _, _, 0x2) => "8XY2",
+            (0x8, _, _, 0x3) => "8XY3",
+            (0x8, _, _, 0x4) => "8XY4",
+            (0x8, _, _, 0x5) => "8XY5",
+            (0x8, _, _, 0x6) => "8XY6",
+            (0x8, _, _, 0x7) => "8XY7",
+            (0x8, _, _, 0xE) => "8XYE",
+            (0x9, _, _, 0x0) => "9XY0",
+            (0xA, _, _, _) => "ANNN",
+            (0xB, _, _, _) => "BNNN",
+            (0xC, _, _, _) => "CXNN",
+            (0xD, _, _, _) => "DXYN",
+            (0xE, _, 0x9, 0xE) => "EX9E",
+            (0xE, _, 0xA, 0x1) => "EXA1",
+            (0xF, _, 0x0, 0x7) => "FX07",
+            (0xF, _, 0x0, 0xA) => "FX0A",
+            (0xF, _, 0x1, 0x5) => "FX15",
+            (0xF, _, 0x1, 0x8) => "FX18",
+            (0xF, _, 0x1, 0xE) => "FX1E",
+            (0xF, _, 0x2, 0x9) => "FX29",
+            (0xF, _, 0x3, 0x3) => "FX33",
+            (0xF, _, 0x5, 0x5) => "FX55",
+            (0xF, _, 0x6, 0x5) => "FX65",
+            _ => "????",
+        }
+    }
 }
@@ -0,0 +1,374 @@
+use std::path::Path;
+use std::process;
+
+use crate::config::Config;
+use crate::palette::Palette;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+
+// Where the launcher looks for roms, when none is given
+const DEFAULT_ROM_DIRECTORY: &str = "roms";
+
+// How many times bigger than the screen the window starts
+const DEFAULT_SCALE: u32 = 10;
+
+const USAGE: &str = "\
+Usage: rchip8 [options] [<rom_file> | <directory>]
+       rchip8 disasm [--platform <platform>] [--recursive | --source] <rom_file>
+       rchip8 asm [-o <rom_file>] <source_file>
+       rchip8 octo [-o <rom_file>] <source_file>
+       rchip8 info <rom_file>
+       rchip8 cfg [--platform <platform>] [-o <dot_file>] <rom_file>
+
+Commands:
+    disasm                Print the address, raw bytes and mnemonic of every word of the rom
+    asm                   Assemble Cowgod style mnemonics into a rom
+    octo                  Compile Octo source into a rom
+    info                  Detect the platform and quirks a rom needs, and recommend settings
+    cfg                   Write the control flow graph of the rom as a Graphviz DOT file
+
+Disassembler options:
+    --platform <platform> One of chip8 (default), schip or xochip
+    --recursive           Follow the flow of control, and emit Octo source which reassembles to the rom
+    --source              Emit source for the assembler, which reassembles to the rom
+
+Control flow graph options:
+    --platform <platform> One of chip8 (default), schip or xochip
+    -o <dot_file>         Where to write the graph (defaults to standard output)
+
+Assembler and Octo options:
+    -o <rom_file>         Where to write the rom (defaults to <source_file> with a .ch8 extension)
+
+Options:
+    Given a directory (roms by default), a menu of its roms is shown to choose from
+    <rom_file> may also be Octo source ending in .8o, which is compiled and its labels used as symbols
+    --platform <platform> One of chip8 (default), schip or xochip, which sets the default quirks and speed
+    --quirks <preset>     One of modern (default), cosmac, schip or xochip
+    --cpu-hz <hz>         Instructions per second (defaults to 700 for chip8)
+    --auto                Use the settings recommended by info, unless given on the command line
+    --patch <file>        Apply an IPS or BPS patch to the rom as it is loaded, leaving the file untouched
+    --watch               Reload the rom whenever its file changes, keeping the window and speed
+    --watch-reset         As --watch, but also reset the speed, unpause and recentre the window on reload
+    --persistence <count> Fade pixels out over <count> frames after they turn off, to hide flicker
+    --theme <theme>       One of default, octo, lcd, amber or high-contrast, used unless the rom has its own colours
+    --colors <colors>     Colours as #RRGGBB: the background and fill, then optionally XO-CHIP's fill2 and blend
+    --config <file>       Read defaults such as the theme from <file> (defaults to ~/.config/rchip8/config)
+    --cheats <file>       Load and save cheats in <file> (defaults to <rom_file> with a .cht extension)
+    --headless            Run without a window as fast as possible, then print the last frame
+    --frames <count>      Stop a headless run after <count> frames (defaults to running until it crashes)
+    --scale <scale>       Open the window <scale> times the size of the screen (defaults to 10)
+    --integer-scale       Only draw pixels a whole number of window pixels big when the window is resized
+    --terminal            Draw the screen in the terminal with half-block characters, and read keys from it
+    --braille             As --terminal, but with braille characters, which are half the size
+    --profile <prefix>    Profile execution, writing <prefix>.txt and <prefix>.folded on exit
+    --coverage <file>     Write rom coverage on exit, as a memory map image if <file> ends in .ppm
+    --symbols <file>      Load labels from a symbol file (defaults to <rom_file> with a .sym extension)
+    --trace               Print every executed instruction
+    --crash-report <file> Also write the crash report to <file> if execution fails";
+
+pub enum Command {
+    Run(Options),
+    Disassemble(DisassembleOptions),
+    Assemble(SourceOptions),
+    CompileOcto(SourceOptions),
+    Info(InfoOptions),
+    Graph(GraphOptions),
+}
+
+impl Command {
+    // Parse the command line, exiting with usage information if it is invalid
+    pub fn from_args(args: Vec<String>) -> Self {
+        match Command::parse(args) {
+            Ok(command) => command,
+            Err(message) => {
+                eprintln!("{}\n\n{}", message, USAGE);
+                process::exit(1);
+            }
+        }
+    }
+
+    fn parse(args: Vec<String>) -> Result<Self, String> {
+        let mut args = args.into_iter().skip(1).peekable();
+        match args.peek().map(String::as_str) {
+            Some("disasm") => Ok(Command::Disassemble(DisassembleOptions::parse(args.skip(1))?)),
+            Some("asm") => Ok(Command::Assemble(SourceOptions::parse(args.skip(1))?)),
+            Some("cfg") => Ok(Command::Graph(GraphOptions::parse(args.skip(1))?)),
+            Some("info") => Ok(Command::Info(InfoOptions::parse(args.skip(1))?)),
+            Some("octo") => Ok(Command::CompileOcto(SourceOptions::parse(args.skip(1))?)),
+            _ => Ok(Command::Run(Options::parse(args)?)),
+        }
+    }
+}
+
+pub struct DisassembleOptions {
+    pub rom_file: String,
+    pub platform: Platform,
+    pub recursive: bool,
+    pub source: bool,
+}
+
+impl DisassembleOptions {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom_file = None;
+        let mut platform = Platform::Chip8;
+        let mut recursive = false;
+        let mut source = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--platform" => platform = parse_platform(&next_value(&mut args, &arg)?)?,
+                "--recursive" => recursive = true,
+                "--source" => source = true,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+                _ if rom_file.is_none() => rom_file = Some(arg),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+
+        Ok(Self {
+            rom_file: rom_file.ok_or("No rom file given")?,
+            platform,
+            recursive,
+            source,
+        })
+    }
+}
+
+pub struct SourceOptions {
+    pub source_file: String,
+    pub output_file: String,
+}
+
+impl SourceOptions {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut source_file = None;
+        let mut output_file = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => output_file = Some(next_value(&mut args, &arg)?),
+                flag if flag.starts_with('-') => return Err(format!("Unknown option {}", flag)),
+                _ if source_file.is_none() => source_file = Some(arg),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+
+        let source_file: String = source_file.ok_or("No source file given")?;
+        let output_file = output_file.unwrap_or_else(|| {
+            Path::new(&source_file).with_extension("ch8").to_string_lossy().into_owned()
+        });
+
+        Ok(Self {
+            source_file,
+            output_file,
+        })
+    }
+}
+
+pub struct InfoOptions {
+    pub rom_file: String,
+}
+
+impl InfoOptions {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom_file = None;
+
+        for arg in args {
+            match arg.as_str() {
+                flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+                _ if rom_file.is_none() => rom_file = Some(arg),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+
+        Ok(Self {
+            rom_file: rom_file.ok_or("No rom file given")?,
+        })
+    }
+}
+
+pub struct GraphOptions {
+    pub rom_file: String,
+    pub platform: Platform,
+    pub output_file: Option<String>,
+}
+
+impl GraphOptions {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom_file = None;
+        let mut platform = Platform::Chip8;
+        let mut output_file = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--platform" => platform = parse_platform(&next_value(&mut args, &arg)?)?,
+                "-o" | "--output" => output_file = Some(next_value(&mut args, &arg)?),
+                flag if flag.starts_with('-') => return Err(format!("Unknown option {}", flag)),
+                _ if rom_file.is_none() => rom_file = Some(arg),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+
+        Ok(Self {
+            rom_file: rom_file.ok_or("No rom file given")?,
+            platform,
+            output_file,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub rom_file: String,
+    pub profile: Option<String>,
+    pub coverage: Option<String>,
+    pub symbols: Option<String>,
+    pub trace: bool,
+    pub crash_report: Option<String>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub cpu_hz: Option<u64>,
+    pub auto: bool,
+    pub patch: Option<String>,
+    pub cheats: Option<String>,
+    pub watch: bool,
+    pub watch_reset: bool,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub terminal: bool,
+    pub braille: bool,
+    pub palette: Option<Palette>,
+    pub config: Config,
+    pub scale: u32,
+    pub integer_scale: bool,
+    pub persistence: u32,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom_file = None;
+        let mut profile = None;
+        let mut coverage = None;
+        let mut symbols = None;
+        let mut trace = false;
+        let mut crash_report = None;
+        let mut platform = None;
+        let mut quirks = None;
+        let mut cpu_hz = None;
+        let mut auto = false;
+        let mut patch = None;
+        let mut cheats = None;
+        let mut watch = false;
+        let mut watch_reset = false;
+        // Builds without SDL can only run headless
+        let mut headless = !cfg!(feature = "sdl");
+        let mut frames = None;
+        let mut terminal = false;
+        let mut braille = false;
+        let mut palette = None;
+        let mut config_file = None;
+        let mut scale = DEFAULT_SCALE;
+        let mut integer_scale = false;
+        let mut persistence = 0;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--profile" => profile = Some(next_value(&mut args, &arg)?),
+                "--coverage" => coverage = Some(next_value(&mut args, &arg)?),
+                "--symbols" => symbols = Some(next_value(&mut args, &arg)?),
+                "--trace" => trace = true,
+                "--crash-report" => crash_report = Some(next_value(&mut args, &arg)?),
+                "--platform" => platform = Some(parse_platform(&next_value(&mut args, &arg)?)?),
+                "--quirks" => {
+                    let name = next_value(&mut args, &arg)?;
+                    quirks = Some(Quirks::from_name(&name).ok_or(format!("Unknown quirk preset {}", name))?);
+                },
+                "--cpu-hz" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(hz) if hz > 0 => cpu_hz = Some(hz),
+                        _ => return Err(format!("Invalid speed {}", value)),
+                    }
+                },
+                "--auto" => auto = true,
+                "--patch" => patch = Some(next_value(&mut args, &arg)?),
+                "--cheats" => cheats = Some(next_value(&mut args, &arg)?),
+                "--watch" => watch = true,
+                "--watch-reset" => {
+                    watch = true;
+                    watch_reset = true;
+                },
+                "--headless" => headless = true,
+                "--frames" => {
+                    let value = next_value(&mut args, &arg)?;
+                    frames = Some(value.parse().map_err(|_| format!("Invalid frame count {}", value))?);
+                },
+                "--theme" => {
+                    let name = next_value(&mut args, &arg)?;
+                    palette = Some(Palette::from_name(&name).ok_or(format!("Unknown theme {}", name))?);
+                },
+                "--colors" => palette = Some(Palette::parse(&next_value(&mut args, &arg)?)?),
+                "--config" => config_file = Some(next_value(&mut args, &arg)?),
+                "--scale" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(value) if value > 0 && value <= 100 => scale = value,
+                        _ => return Err(format!("Invalid scale {}", value)),
+                    }
+                },
+                "--integer-scale" => integer_scale = true,
+                "--persistence" => {
+                    let value = next_value(&mut args, &arg)?;
+                    persistence = value.parse().map_err(|_| format!("Invalid frame count {}", value))?;
+                },
+                "--terminal" => terminal = true,
+                "--braille" => {
+                    terminal = true;
+                    braille = true;
+                },
+                flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+                _ if rom_file.is_none() => rom_file = Some(arg),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+
+        // A headless run has nothing to keep between reloads
+        if watch && headless && !terminal {
+            return Err("--watch needs the window or --terminal".to_string());
+        }
+
+        Ok(Self {
+            rom_file: rom_file.unwrap_or_else(|| DEFAULT_ROM_DIRECTORY.to_string()),
+            profile,
+            coverage,
+            symbols,
+            trace,
+            crash_report,
+            platform,
+            quirks,
+            cpu_hz,
+            auto,
+            patch,
+            cheats,
+            watch,
+            watch_reset,
+            headless,
+            frames,
+            terminal,
+            braille,
+            palette,
+            config: Config::load(config_file.as_deref())?,
+            scale,
+            integer_scale,
+            persistence,
+        })
+    }
+}
+
+fn parse_platform(name: &str) -> Result<Platform, String> {
+    Platform::from_name(name).ok_or(format!("Unknown platform {}", name))
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or(format!("Missing value for {}", flag))
+}
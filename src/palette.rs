@@ -0,0 +1,115 @@
+pub type Color = (u8, u8, u8);
+
+// The colours of the screen: the background, pixels set in the first
+// bitplane, pixels set in XO-CHIP's second bitplane, and pixels set in both
+#[derive(Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub background: Color,
+    pub fill: Color,
+    pub fill2: Color,
+    pub blend: Color,
+}
+
+// Named palettes, in the order the theme hotkey cycles through them
+const THEMES: [(&str, Palette); 5] = [
+    ("default", Palette {
+        background: (0x00, 0x00, 0x00),
+        fill: (0xFF, 0xFF, 0xFF),
+        fill2: (0xAA, 0xAA, 0xAA),
+        blend: (0x55, 0x55, 0x55),
+    }),
+    ("octo", Palette {
+        background: (0x99, 0x66, 0x00),
+        fill: (0xFF, 0xCC, 0x00),
+        fill2: (0xFF, 0x66, 0x00),
+        blend: (0x66, 0x22, 0x00),
+    }),
+    ("lcd", Palette {
+        background: (0xF9, 0xFF, 0xB3),
+        fill: (0x3D, 0x80, 0x26),
+        fill2: (0xAB, 0xCC, 0x47),
+        blend: (0x00, 0x13, 0x1A),
+    }),
+    ("amber", Palette {
+        background: (0x1A, 0x0E, 0x00),
+        fill: (0xFF, 0xB0, 0x00),
+        fill2: (0x99, 0x5C, 0x00),
+        blend: (0xFF, 0xE0, 0x99),
+    }),
+    ("high-contrast", Palette {
+        background: (0x00, 0x00, 0x00),
+        fill: (0xFF, 0xFF, 0x00),
+        fill2: (0x00, 0xFF, 0xFF),
+        blend: (0xFF, 0xFF, 0xFF),
+    }),
+];
+
+impl Default for Palette {
+    fn default() -> Self {
+        THEMES[0].1
+    }
+}
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Self> {
+        THEMES.iter().find(|(theme, _)| *theme == name).map(|(_, palette)| *palette)
+    }
+
+    // The theme's name, if it is one
+    pub fn name(&self) -> Option<&'static str> {
+        THEMES.iter().find(|(_, palette)| palette == self).map(|(theme, _)| *theme)
+    }
+
+    pub fn theme_names() -> Vec<&'static str> {
+        THEMES.iter().map(|(theme, _)| *theme).collect()
+    }
+
+    // A theme's name, or a comma separated list of #RRGGBB colours: the
+    // background and fill, then optionally the XO-CHIP colours
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if let Some(palette) = Palette::from_name(text) {
+            return Ok(palette);
+        }
+
+        let colors = text.split(',')
+            .map(|color| parse_color(color.trim()).ok_or(format!("Invalid colour {}", color.trim())))
+            .collect::<Result<Vec<_>, _>>()?;
+        Palette::from_colors(&colors).ok_or(format!(
+            "Expected a theme ({}) or 2 to 4 colours, not {}",
+            Palette::theme_names().join(", "),
+            text,
+        ))
+    }
+
+    // Colours in the order they are given by the rom database and Octo, with
+    // the XO-CHIP colours taken from the default palette if missing
+    pub fn from_colors(colors: &[Color]) -> Option<Self> {
+        let default = Palette::default();
+        match colors {
+            [background, fill] => Some(Palette { background: *background, fill: *fill, ..default }),
+            [background, fill, fill2] => Some(Palette { background: *background, fill: *fill, fill2: *fill2, ..default }),
+            [background, fill, fill2, blend] => Some(Palette {
+                background: *background,
+                fill: *fill,
+                fill2: *fill2,
+                blend: *blend,
+            }),
+            _ => None,
+        }
+    }
+
+    // The theme after this one, or the first if this isn't a theme
+    pub fn next_theme(&self) -> Self {
+        let index = THEMES.iter().position(|(_, palette)| palette == self).map_or(0, |index| index + 1);
+        THEMES[index % THEMES.len()].1
+    }
+}
+
+fn parse_color(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
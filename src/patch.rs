@@ -0,0 +1,192 @@
+use std::convert::TryFrom;
+
+use crate::crc32::crc32;
+use crate::state::MAX_ROM_SIZE;
+
+// Apply an IPS or BPS patch to a rom, returning the patched copy
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, &patch[5..])
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err("Not an IPS or BPS patch".to_string())
+    }
+}
+
+// A list of records which each overwrite some bytes, or fill them with one
+// value, growing the rom if they go past its end
+fn apply_ips(rom: &[u8], records: &[u8]) -> Result<Vec<u8>, String> {
+    let mut output = rom.to_vec();
+    let mut reader = Reader::new(records);
+
+    loop {
+        let header = reader.bytes(3).map_err(|_| "IPS patch has no EOF marker")?;
+        if header == b"EOF" {
+            break;
+        }
+        let offset = (header[0] as usize) << 16 | (header[1] as usize) << 8 | header[2] as usize;
+
+        let size = reader.u16_be()? as usize;
+        let (size, data) = if size == 0 {
+            let count = reader.u16_be()? as usize;
+            (count, vec![reader.byte()?; count])
+        } else {
+            (size, reader.bytes(size)?.to_vec())
+        };
+
+        if offset + size > MAX_ROM_SIZE {
+            return Err(format!("IPS record at {:#X} goes past the {} bytes a rom can fill", offset, MAX_ROM_SIZE));
+        }
+        if output.len() < offset + size {
+            output.resize(offset + size, 0);
+        }
+        output[offset..offset + size].copy_from_slice(&data);
+    }
+
+    // An optional length to truncate to
+    if let Ok(length) = reader.bytes(3) {
+        output.truncate((length[0] as usize) << 16 | (length[1] as usize) << 8 | length[2] as usize);
+    }
+
+    Ok(output)
+}
+
+// Actions which build the patched rom out of the original, itself and the
+// patch, with checksums of all three
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < 4 + 12 {
+        return Err("BPS patch is too short".to_string());
+    }
+
+    let (body, footer) = patch.split_at(patch.len() - 12);
+    let footer_crc = |index: usize| u32::from_le_bytes(<[u8; 4]>::try_from(&footer[index * 4..index * 4 + 4]).unwrap());
+    let (source_crc, target_crc, patch_crc) = (footer_crc(0), footer_crc(1), footer_crc(2));
+
+    if crc32(&patch[..patch.len() - 4]) != patch_crc {
+        return Err("BPS patch is corrupt (checksum mismatch)".to_string());
+    }
+    if crc32(rom) != source_crc {
+        return Err(format!("BPS patch is for a different rom (checksum {:08x}, expected {:08x})", crc32(rom), source_crc));
+    }
+
+    let mut reader = Reader::new(&body[4..]);
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(format!("BPS patch is for a {} byte rom, not {} bytes", source_size, rom.len()));
+    }
+    if target_size > MAX_ROM_SIZE {
+        return Err(format!("BPS patch makes a {} byte rom, but at most {} bytes fit", target_size, MAX_ROM_SIZE));
+    }
+
+    let mut output = Vec::with_capacity(target_size);
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+    while !reader.is_empty() {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+        if output.len() + length > target_size {
+            return Err(format!("BPS patch writes past the end of the {} byte rom it makes", target_size));
+        }
+        match action & 3 {
+            // Source read, from the same place in the original
+            0 => {
+                let start = output.len();
+                let bytes = start.checked_add(length)
+                    .and_then(|end| rom.get(start..end))
+                    .ok_or("BPS source read is past the end of the rom")?;
+                output.extend_from_slice(bytes);
+            },
+            // Target read, from the patch itself
+            1 => output.extend_from_slice(reader.bytes(length)?),
+            // Source copy, from anywhere in the original
+            2 => {
+                source_offset = reader.offset(source_offset)?;
+                let bytes = source_offset.checked_add(length)
+                    .and_then(|end| rom.get(source_offset..end))
+                    .ok_or("BPS source copy is past the end of the rom")?;
+                output.extend_from_slice(bytes);
+                source_offset += length;
+            },
+            // Target copy, from earlier in the output, which may overlap what is being written
+            _ => {
+                target_offset = reader.offset(target_offset)?;
+                for _ in 0..length {
+                    let byte = *output.get(target_offset).ok_or("BPS target copy is past the end of the output")?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            },
+        }
+    }
+
+    if output.len() != target_size {
+        return Err(format!("BPS patch produced {} bytes instead of {}", output.len(), target_size));
+    }
+    if crc32(&output) != target_crc {
+        return Err("BPS patch produced the wrong rom (checksum mismatch)".to_string());
+    }
+
+    Ok(output)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self.position.checked_add(count)
+            .and_then(|end| self.data.get(self.position..end))
+            .ok_or("Patch ended unexpectedly")?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_be(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    // BPS's variable length numbers, 7 bits at a time with the top bit
+    // marking the last byte
+    fn number(&mut self) -> Result<usize, String> {
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()? as usize;
+            number = (byte & 0x7F).checked_mul(shift)
+                .and_then(|value| number.checked_add(value))
+                .ok_or("BPS number is too large")?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(128).ok_or("BPS number is too large")?;
+            number = number.checked_add(shift).ok_or("BPS number is too large")?;
+        }
+    }
+
+    // A signed step from an offset, with the sign in the lowest bit
+    fn offset(&mut self, offset: usize) -> Result<usize, String> {
+        let step = self.number()?;
+        let distance = step >> 1;
+        let offset = if step & 1 != 0 { offset.checked_sub(distance) } else { offset.checked_add(distance) };
+        offset.ok_or_else(|| "BPS copy offset is out of range".to_string())
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use crate::op_code::OpCode;
+use crate::symbols::Symbols;
+
+// Entry point of the rom, treated as the root of the call graph
+const ENTRY_POINT: u16 = 0x200;
+
+// How many of the hottest addresses to list in the report
+const NUM_HOTSPOTS: usize = 32;
+
+#[derive(Default)]
+struct FunctionStats {
+    calls: u64,
+    inclusive_cycles: u64,
+    exclusive_cycles: u64,
+}
+
+// Counts every executed instruction, and follows 2NNN/00EE to attribute
+// cycles to subroutines.
+pub struct Profiler {
+    total_cycles: u64,
+    address_counts: Vec<u64>,
+    address_patterns: Vec<&'static str>,
+    class_counts: HashMap<&'static str, u64>,
+    functions: HashMap<u16, FunctionStats>,
+    call_edges: HashMap<(u16, u16), u64>,
+    folded_stacks: HashMap<Vec<u16>, u64>,
+    call_stack: Vec<u16>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        let mut functions = HashMap::new();
+        functions.insert(ENTRY_POINT, FunctionStats { calls: 1, ..FunctionStats::default() });
+
+        Self {
+            total_cycles: 0,
+            address_counts: vec![0; 4096],
+            address_patterns: vec!["????"; 4096],
+            class_counts: HashMap::new(),
+            functions,
+            call_edges: HashMap::new(),
+            folded_stacks: HashMap::new(),
+            call_stack: vec![ENTRY_POINT],
+        }
+    }
+
+    // Record an instruction which is about to be executed from address pc
+    pub fn record(&mut self, pc: u16, op_code: &OpCode) {
+        let pattern = op_code.pattern();
+
+        self.total_cycles += 1;
+        self.address_counts[usize::from(pc) % 4096] += 1;
+        self.address_patterns[usize::from(pc) % 4096] = pattern;
+        *self.class_counts.entry(pattern).or_insert(0) += 1;
+
+        // Every function on the stack is running, but recursive functions
+        // should only be counted once
+        let mut seen = Vec::with_capacity(self.call_stack.len());
+        for function in &self.call_stack {
+            if !seen.contains(function) {
+                seen.push(*function);
+                self.functions.entry(*function).or_default().inclusive_cycles += 1;
+            }
+        }
+
+        let current = *self.call_stack.last().unwrap();
+        self.functions.entry(current).or_default().exclusive_cycles += 1;
+        *self.folded_stacks.entry(self.call_stack.clone()).or_insert(0) += 1;
+
+        match pattern {
+            "2NNN" => {
+                self.functions.entry(op_code.nnn).or_default().calls += 1;
+                *self.call_edges.entry((current, op_code.nnn)).or_insert(0) += 1;
+                self.call_stack.push(op_code.nnn);
+            },
+            // Never pop the entry point, even if the rom returns from it
+            "00EE" if self.call_stack.len() > 1 => {
+                self.call_stack.pop();
+            },
+            _ => {},
+        }
+    }
+
+    // Write a human readable report to <prefix>.txt, and folded stacks
+    // suitable for flamegraph.pl or inferno to <prefix>.folded
+    pub fn write_reports(&self, prefix: &str, symbols: &Symbols) -> io::Result<()> {
+        fs::write(format!("{}.txt", prefix), self.text_report(symbols))?;
+        fs::write(format!("{}.folded", prefix), self.folded_report(symbols))?;
+        Ok(())
+    }
+
+    fn percent(&self, cycles: u64) -> f64 {
+        if self.total_cycles == 0 {
+            return 0.0;
+        }
+        100.0 * cycles as f64 / self.total_cycles as f64
+    }
+
+    fn text_report(&self, symbols: &Symbols) -> String {
+        let mut report = format!("Total cycles: {}\n", self.total_cycles);
+
+        report.push_str("\n=== Hottest addresses\n");
+        report.push_str("Address  Op    Cycles        %  Symbol\n");
+        let mut addresses: Vec<usize> = (0..self.address_counts.len())
+            .filter(|address| self.address_counts[*address] > 0)
+            .collect();
+        addresses.sort_by(|a, b| self.address_counts[*b].cmp(&self.address_counts[*a]).then(a.cmp(b)));
+        for address in addresses.iter().take(NUM_HOTSPOTS) {
+            let cycles = self.address_counts[*address];
+            report.push_str(&format!(
+                "{:#05X}    {}  {:>10}  {:>6.2}  {}\n",
+                address,
+                self.address_patterns[*address],
+                cycles,
+                self.percent(cycles),
+                symbols.resolve(*address as u16),
+            ));
+        }
+
+        report.push_str("\n=== Op code classes\n");
+        report.push_str("Op        Cycles        %\n");
+        let mut classes: Vec<(&&str, &u64)> = self.class_counts.iter().collect();
+        classes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (class, cycles) in classes {
+            report.push_str(&format!("{}  {:>10}  {:>6.2}\n", class, cycles, self.percent(*cycles)));
+        }
+
+        report.push_str("\n=== Subroutines\n");
+        report.push_str("Address   Calls   Inclusive        %   Exclusive        %  Symbol\n");
+        let mut functions: Vec<(&u16, &FunctionStats)> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.inclusive_cycles.cmp(&a.1.inclusive_cycles).then(a.0.cmp(b.0)));
+        for (address, stats) in functions {
+            report.push_str(&format!(
+                "{:#05X}  {:>6}  {:>10}  {:>6.2}  {:>10}  {:>6.2}  {}\n",
+                address,
+                stats.calls,
+                stats.inclusive_cycles,
+                self.percent(stats.inclusive_cycles),
+                stats.exclusive_cycles,
+                self.percent(stats.exclusive_cycles),
+                symbols.resolve(*address),
+            ));
+        }
+
+        report.push_str("\n=== Call graph\n");
+        report.push_str("Caller -> Callee   Calls\n");
+        let mut edges: Vec<(&(u16, u16), &u64)> = self.call_edges.iter().collect();
+        edges.sort();
+        for ((caller, callee), calls) in edges {
+            report.push_str(&format!(
+                "{:#05X} -> {:#05X}  {:>7}  {} -> {}\n",
+                caller, callee, calls, symbols.resolve(*caller), symbols.resolve(*callee),
+            ));
+        }
+
+        report
+    }
+
+    fn folded_report(&self, symbols: &Symbols) -> String {
+        let mut lines: Vec<String> = self.folded_stacks.iter()
+            .map(|(stack, cycles)| {
+                let frames: Vec<String> = stack.iter()
+                    .map(|address| symbols.resolve(*address))
+                    .collect();
+                format!("{} {}\n", frames.join(";"), cycles)
+            })
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
@@ -0,0 +1,91 @@
+use crate::platform::Platform;
+
+// Behaviours which differ between CHIP-8 interpreters, and which roms can
+// rely on without realising
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6 and 8XYE shift VY into VX, rather than shifting VX in place
+    pub shift_uses_vy: bool,
+    // FX55 and FX65 leave I pointing after the last register
+    pub load_store_increments_i: bool,
+    // BNNN jumps to XNN + VX, rather than NNN + V0
+    pub jump_uses_vx: bool,
+    // 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub vf_reset: bool,
+}
+
+pub const PRESET_NAMES: [&str; 4] = ["modern", "cosmac", "schip", "xochip"];
+
+impl Quirks {
+    // What most modern interpreters do, and what rchip8 does by default
+    pub fn modern() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            vf_reset: false,
+        }
+    }
+
+    // The original COSMAC VIP interpreter
+    pub fn cosmac() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: true,
+        }
+    }
+
+    pub fn schip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            vf_reset: false,
+        }
+    }
+
+    pub fn xochip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: false,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "modern" => Some(Quirks::modern()),
+            "cosmac" | "vip" => Some(Quirks::cosmac()),
+            "schip" | "superchip" | "super-chip" => Some(Quirks::schip()),
+            "xochip" | "xo-chip" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+
+    // The preset these quirks match, if any
+    pub fn name(&self) -> Option<&'static str> {
+        PRESET_NAMES.iter()
+            .find(|name| Quirks::from_name(name) == Some(*self))
+            .copied()
+    }
+
+    pub fn for_platform(platform: Platform) -> Self {
+        match platform {
+            Platform::Chip8 => Quirks::modern(),
+            Platform::Schip => Quirks::schip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+}
+
+// Instructions per second which roms for the platform typically expect
+pub fn default_cpu_hz(platform: Platform) -> u64 {
+    match platform {
+        Platform::Chip8 => 700,
+        Platform::Schip => 1800,
+        Platform::XoChip => 60000,
+    }
+}
@@ -0,0 +1,10 @@
+use crate::palette::Palette;
+
+// Somewhere to show the machine's screen
+pub trait Renderer {
+    fn draw_frame(&mut self, frame_buffer: &[[bool; 32]; 64]);
+
+    fn set_title(&mut self, title: &str);
+
+    fn set_palette(&mut self, palette: &Palette);
+}
@@ -0,0 +1,143 @@
+use crate::json::Json;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::sha1;
+
+// A copy of the roms in roms/, in the format of the community CHIP-8 database
+// (https://github.com/chip-8/chip-8-database): a list of programs, and the
+// index of the program for each rom's SHA-1 hash
+const PROGRAMS: &str = include_str!("../database/programs.json");
+const HASHES: &str = include_str!("../database/sha1-hashes.json");
+
+// Frames per second, for turning the database's instructions per frame into
+// instructions per second
+const FRAME_RATE: u64 = 60;
+
+pub struct Entry {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub cpu_hz: u64,
+    // Background then foreground, and any further colours for XO-CHIP planes
+    pub colors: Vec<(u8, u8, u8)>,
+    // Names of inputs, such as "up" or "a", and the CHIP-8 key each one is
+    pub keys: Vec<(String, u8)>,
+}
+
+// Look up a rom in the bundled database
+pub fn lookup(rom: &[u8]) -> Result<Option<Entry>, String> {
+    lookup_in(PROGRAMS, HASHES, &sha1::sha1_hex(rom))
+}
+
+fn lookup_in(programs: &str, hashes: &str, hash: &str) -> Result<Option<Entry>, String> {
+    let hashes = Json::parse(hashes).map_err(|e| format!("Invalid rom database hashes: {}", e))?;
+    let index = match hashes.get(hash).and_then(Json::as_f64) {
+        Some(index) => index as usize,
+        None => return Ok(None),
+    };
+
+    let programs = Json::parse(programs).map_err(|e| format!("Invalid rom database programs: {}", e))?;
+    let program = programs.as_array()
+        .and_then(|programs| programs.get(index))
+        .ok_or(format!("Rom database has no program {} for {}", index, hash))?;
+    let rom = program.get("roms")
+        .and_then(|roms| roms.get(hash))
+        .ok_or(format!("Rom database program {} doesn't list {}", index, hash))?;
+
+    // The first platform which rchip8 knows about
+    let platform_ids = rom.get("platforms").and_then(Json::as_array).unwrap_or(&[]);
+    let (platform_id, (platform, mut quirks, tickrate)) = platform_ids.iter()
+        .filter_map(Json::as_str)
+        .find_map(|id| platform_defaults(id).map(|defaults| (id, defaults)))
+        .ok_or(format!("Rom database has no supported platform for {}", hash))?;
+
+    let overrides = rom.get("quirkyPlatforms")
+        .and_then(|quirky| quirky.get(platform_id))
+        .and_then(Json::as_object)
+        .unwrap_or(&[]);
+    for (name, value) in overrides {
+        apply_quirk(&mut quirks, name, value.as_bool().unwrap_or(false));
+    }
+
+    let tickrate = rom.get("tickrate").and_then(Json::as_f64).map(|tickrate| tickrate as u64).unwrap_or(tickrate);
+
+    let colors = rom.get("colors")
+        .and_then(|colors| colors.get("pixels"))
+        .and_then(Json::as_array)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|color| color.as_str().and_then(parse_color))
+        .collect();
+
+    let keys = rom.get("keys")
+        .and_then(Json::as_object)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|(name, key)| key.as_f64().map(|key| (name.clone(), key as u8)))
+        .collect();
+
+    Ok(Some(Entry {
+        title: program.get("title").and_then(Json::as_str).unwrap_or("Unknown").to_string(),
+        authors: program.get("authors")
+            .and_then(Json::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|author| author.as_str().map(String::from))
+            .collect(),
+        platform,
+        quirks,
+        cpu_hz: tickrate * FRAME_RATE,
+        colors,
+        keys,
+    }))
+}
+
+// The platform, quirks and instructions per frame of each of the database's
+// platforms which rchip8 can run
+fn platform_defaults(id: &str) -> Option<(Platform, Quirks, u64)> {
+    let modern_chip8 = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        vf_reset: false,
+    };
+    let chip48 = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: true,
+        jump_uses_vx: true,
+        vf_reset: false,
+    };
+
+    match id {
+        "originalChip8" | "hybridVIP" => Some((Platform::Chip8, Quirks::cosmac(), 15)),
+        "modernChip8" => Some((Platform::Chip8, modern_chip8, 12)),
+        "chip48" => Some((Platform::Chip8, chip48, 30)),
+        "superchip1" | "superchip" => Some((Platform::Schip, Quirks::schip(), 30)),
+        "xochip" => Some((Platform::XoChip, Quirks::xochip(), 1000)),
+        _ => None,
+    }
+}
+
+// The database's quirks are named after how later interpreters differ from
+// the original
+fn apply_quirk(quirks: &mut Quirks, name: &str, enabled: bool) {
+    match name {
+        "shift" => quirks.shift_uses_vy = !enabled,
+        "memoryLeaveIUnchanged" => quirks.load_store_increments_i = !enabled,
+        // I is incremented one less than usual, which is close enough
+        "memoryIncrementByX" => quirks.load_store_increments_i = quirks.load_store_increments_i || enabled,
+        "jump" => quirks.jump_uses_vx = enabled,
+        "logic" => quirks.vf_reset = enabled,
+        _ => {},
+    }
+}
+
+fn parse_color(text: &str) -> Option<(u8, u8, u8)> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
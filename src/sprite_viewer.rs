@@ -0,0 +1,169 @@
+extern crate sdl2;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::Sdl;
+use sdl2::video::Window;
+
+use crate::coverage::{self, Coverage};
+use crate::platform::Platform;
+
+const RAM_SIZE: usize = 4096;
+
+// Each column of the viewer is a vertical strip of sprite rows
+const ROWS_PER_COLUMN: u32 = 64;
+const COLUMN_GAP: u32 = 2;
+const SCALE: u32 = 4;
+const WIDTH: u32 = 16 * (8 + COLUMN_GAP) * SCALE;
+const HEIGHT: u32 = ROWS_PER_COLUMN * SCALE;
+
+// Renders a region of ram as if every byte were sprite data, highlighting the
+// bytes that DXYN has actually read so far.
+pub struct SpriteViewer {
+    canvas: Canvas<Window>,
+    start: usize,
+    wide: bool,
+    background_color: Color,
+    pixel_color: Color,
+    sprite_background_color: Color,
+    sprite_pixel_color: Color,
+}
+
+impl SpriteViewer {
+    // Starts showing 16x16 sprites for the platforms which can draw them
+    pub fn new(sdl_context: &Sdl, start: usize, platform: Platform) -> Self {
+        let video_subsystem = sdl_context.video().unwrap();
+
+        let window = video_subsystem.window("Sprite viewer", WIDTH, HEIGHT)
+            .build()
+            .unwrap();
+
+        let canvas = window.into_canvas().build().unwrap();
+
+        Self {
+            canvas,
+            start,
+            wide: matches!(platform, Platform::Schip | Platform::XoChip),
+            background_color: Color::RGB(0, 0, 0),
+            pixel_color: Color::RGB(160, 160, 160),
+            sprite_background_color: Color::RGB(0, 48, 0),
+            sprite_pixel_color: Color::RGB(0, 255, 0),
+        }
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    // Switch between 8 pixel wide sprites and 16x16 (SCHIP) sprites
+    pub fn toggle_wide(&mut self) {
+        self.wide = !self.wide;
+    }
+
+    // Scroll by a single sprite row
+    pub fn scroll_rows(&mut self, rows: i32) {
+        self.scroll(rows * self.bytes_per_row() as i32);
+    }
+
+    // Scroll by a whole column of sprite rows
+    pub fn scroll_columns(&mut self, columns: i32) {
+        self.scroll(columns * (self.bytes_per_row() * ROWS_PER_COLUMN as usize) as i32);
+    }
+
+    fn scroll(&mut self, bytes: i32) {
+        let start = self.start as i32 + bytes;
+        self.start = start.max(0).min(RAM_SIZE as i32 - 1) as usize;
+    }
+
+    fn bytes_per_row(&self) -> usize {
+        if self.wide { 2 } else { 1 }
+    }
+
+    fn num_columns(&self) -> u32 {
+        let column_width = (8 * self.bytes_per_row() as u32 + COLUMN_GAP) * SCALE;
+        WIDTH / column_width
+    }
+
+    pub fn draw(&mut self, ram: &[u8; 4096], coverage: &Coverage) {
+        let bytes_per_row = self.bytes_per_row();
+        let column_width = 8 * bytes_per_row as u32 + COLUMN_GAP;
+        let end = (self.start + self.num_columns() as usize * ROWS_PER_COLUMN as usize * bytes_per_row)
+            .min(RAM_SIZE);
+
+        let mut pixels = Vec::new();
+        let mut sprite_backgrounds = Vec::new();
+        let mut sprite_pixels = Vec::new();
+
+        for (offset, byte) in ram[self.start..end].iter().enumerate() {
+            let address = self.start + offset;
+            let row = (offset / bytes_per_row) as u32;
+            let column = row / ROWS_PER_COLUMN;
+            let x = (column * column_width + 8 * (offset % bytes_per_row) as u32) * SCALE;
+            let y = (row % ROWS_PER_COLUMN) * SCALE;
+
+            let was_read = coverage.has(address, coverage::SPRITE);
+            if was_read {
+                sprite_backgrounds.push(Rect::new(x as i32, y as i32, 8 * SCALE, SCALE));
+            }
+
+            for bit in 0..8 {
+                if byte & (1 << (7 - bit)) == 0 {
+                    continue;
+                }
+
+                let pixel = Rect::new((x + bit * SCALE) as i32, y as i32, SCALE, SCALE);
+                if was_read {
+                    sprite_pixels.push(pixel);
+                } else {
+                    pixels.push(pixel);
+                }
+            }
+        }
+
+        self.canvas.set_draw_color(self.background_color);
+        self.canvas.clear();
+
+        self.canvas.set_draw_color(self.sprite_background_color);
+        self.canvas.fill_rects(&sprite_backgrounds).expect("Failed to draw sprite background");
+        self.canvas.set_draw_color(self.pixel_color);
+        self.canvas.fill_rects(&pixels).expect("Failed to draw pixels");
+        self.canvas.set_draw_color(self.sprite_pixel_color);
+        self.canvas.fill_rects(&sprite_pixels).expect("Failed to draw sprite pixels");
+
+        self.canvas.present();
+
+        let title = format!(
+            "Sprite viewer {:#05X}-{:#05X} ({})",
+            self.start,
+            end - 1,
+            if self.wide { "16x16" } else { "8xN" },
+        );
+        self.canvas.window_mut().set_title(&title).expect("Failed to set title");
+    }
+}
+
+// Print every contiguous region of ram that DXYN has read, so that a ROM's
+// art can be extracted
+pub fn dump_sprite_regions(ram: &[u8; 4096], coverage: &Coverage) {
+    let mut address = 0;
+    while address < RAM_SIZE {
+        if !coverage.has(address, coverage::SPRITE) {
+            address += 1;
+            continue;
+        }
+
+        let start = address;
+        while address < RAM_SIZE && coverage.has(address, coverage::SPRITE) {
+            address += 1;
+        }
+
+        println!("{:#05X}-{:#05X} ({} bytes)", start, address - 1, address - start);
+        for byte in &ram[start..address] {
+            let row: String = (0..8)
+                .map(|bit| if byte & (1 << (7 - bit)) != 0 { '#' } else { '.' })
+                .collect();
+            println!("    {:#04X}  {}", byte, row);
+        }
+    }
+}
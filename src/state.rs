@@ -1,6 +1,11 @@
 use rand::Rng;
 
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::coverage::{self, Coverage};
 use crate::op_code::OpCode;
+use crate::quirks::Quirks;
 
 macro_rules! debug {
     ($( $args:expr ),*) => {
@@ -8,6 +13,29 @@ macro_rules! debug {
     }
 }
 
+// How many recently executed instructions to remember, for crash reports
+const HISTORY_SIZE: usize = 32;
+
+// Roms are loaded at 0x200 and can fill the rest of memory
+pub const MAX_ROM_SIZE: usize = 0x1000 - 0x200;
+
+#[derive(Debug)]
+pub enum ExecutionError {
+    UnknownOpCode(u16),
+    StackUnderflow,
+    MemoryOutOfBounds(usize),
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecutionError::UnknownOpCode(word) => write!(f, "Unknown op code {:04X}", word),
+            ExecutionError::StackUnderflow => write!(f, "Return with nothing on the stack"),
+            ExecutionError::MemoryOutOfBounds(address) => write!(f, "Memory access out of bounds at {:#05X}", address),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct State {
     display: [[bool; 32]; 64],
@@ -19,6 +47,10 @@ pub struct State {
     sound_timer: u8,
     v: [u8; 16],
     keypad: [bool; 16],
+    rom_size: usize,
+    coverage: Coverage,
+    history: VecDeque<(u16, u16)>,
+    quirks: Quirks,
 }
 
 impl State {
@@ -33,6 +65,10 @@ impl State {
             sound_timer: 0,
             v: [0; 16],
             keypad: [false; 16],
+            rom_size: 0,
+            coverage: Coverage::new(),
+            history: VecDeque::with_capacity(HISTORY_SIZE),
+            quirks: Quirks::modern(),
         };
 
         let fonts = [
@@ -64,23 +100,94 @@ impl State {
         for (i, byte) in rom.iter().enumerate() {
             self.ram[0x200 + i] = *byte;
         }
+        self.rom_size = rom.len();
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
 
-    pub fn next_op(self: &mut State) -> OpCode {
-        let byte1 = self.ram[usize::from(self.pc)];
-        let byte2 = self.ram[usize::from(self.pc + 1)];
+    pub fn next_op(self: &mut State) -> Result<OpCode, ExecutionError> {
+        let byte1 = self.read_ram(usize::from(self.pc), coverage::FETCHED)?;
+        let byte2 = self.read_ram(usize::from(self.pc) + 1, coverage::FETCHED)?;
 
         let next_op = OpCode::from_bytes(byte1, byte2);
 
+        if self.history.len() == HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back((self.pc, next_op.word()));
+
         self.pc += 2;
 
-        return next_op;
+        return Ok(next_op);
+    }
+
+    fn read_ram(&mut self, address: usize, access: u8) -> Result<u8, ExecutionError> {
+        let byte = *self.ram.get(address).ok_or(ExecutionError::MemoryOutOfBounds(address))?;
+        self.coverage.mark(address, access);
+        Ok(byte)
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8) -> Result<(), ExecutionError> {
+        let byte = self.ram.get_mut(address).ok_or(ExecutionError::MemoryOutOfBounds(address))?;
+        *byte = value;
+        self.coverage.mark(address, coverage::WRITTEN);
+        Ok(())
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc
     }
 
     pub fn get_frame_buffer(&self) -> &[[bool; 32]; 64] {
         return &self.display;
     }
 
+    pub fn get_ram(&self) -> &[u8; 4096] {
+        &self.ram
+    }
+
+    // For cheats, which poke values into memory without being seen by coverage
+    pub fn get_ram_mut(&mut self) -> &mut [u8; 4096] {
+        &mut self.ram
+    }
+
+    // Return addresses of every subroutine call in progress
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn get_v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn get_i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // The (address, instruction) of the most recently fetched instructions,
+    // oldest first
+    pub fn get_history(&self) -> &VecDeque<(u16, u16)> {
+        &self.history
+    }
+
+    pub fn get_rom_size(&self) -> usize {
+        self.rom_size
+    }
+
+    pub fn get_coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+
     pub fn get_vx(self: &State, op_code: &OpCode) -> u8 {
         return self.v[usize::from(op_code.x)];
     }
@@ -97,6 +204,18 @@ impl State {
         self.v[0xF] = if value { 1 } else { 0 };
     }
 
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.set_carry(false);
+        }
+    }
+
+    fn increment_i_after_load_store(&mut self, op_code: &OpCode) {
+        if self.quirks.load_store_increments_i {
+            self.i += u16::from(op_code.x) + 1;
+        }
+    }
+
     pub fn decrement_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
@@ -114,7 +233,7 @@ impl State {
         self.keypad[key] = false;
     }
 
-    pub fn execute_op(self: &mut State, op_code: OpCode) {
+    pub fn execute_op(self: &mut State, op_code: OpCode) -> Result<(), ExecutionError> {
         let vx = self.get_vx(&op_code);
         let vy = self.get_vy(&op_code);
         match op_code.op {
@@ -125,10 +244,10 @@ impl State {
                         self.display = [[false; 32]; 64];
                     },
                     0xEu8 => { // Return
-                        self.pc = self.stack.pop().expect("Nothing on the stack to pop!");
+                        self.pc = self.stack.pop().ok_or(ExecutionError::StackUnderflow)?;
                         debug!("00EE: Return to {}", self.pc);
                     },
-                    _ => panic!("Unimplemented op {:?}", op_code),
+                    _ => return Err(ExecutionError::UnknownOpCode(op_code.word())),
                 }
             },
             0x1u8 => { // Jump
@@ -181,14 +300,17 @@ impl State {
                     0x1u8 => { // OR
                         debug!("8XY1: V{}({}) |= V{}({})", op_code.x, vx, op_code.y, vy);
                         self.set_vx(&op_code, vx | vy);
+                        self.reset_vf();
                     },
                     0x2u8 => { // AND
                         debug!("8XY2: V{}({}) &= V{}({})", op_code.x, vx, op_code.y, vy);
                         self.set_vx(&op_code, vx & vy);
+                        self.reset_vf();
                     },
                     0x3u8 => { // XOR
                         debug!("8XY3: V{}({}) ^= V{}({})", op_code.x, vx, op_code.y, vy);
                         self.set_vx(&op_code, vx ^ vy);
+                        self.reset_vf();
                     },
                     0x4u8 => { // Add vy
                         debug!("8XY4: V{}({}) += V{}({})", op_code.x, vx, op_code.y, vy);
@@ -206,26 +328,19 @@ impl State {
                         self.set_carry(vy > vx);
                     },
                     0x6u8 => { // Shift right
-                        // debug!("8XY6: V{}({}) = V{}({} >> 1)", op_code.x, vx, op_code.y, vy);
-                        // self.set_vx(&op_code, vy >> 1);
-                        // self.set_carry(vy & 0b00000001u8 != 0);
-
-                        // We use the "modern" implementation of these instructions
-                        debug!("8XY6: V{}({}) >>= 1", op_code.x, vx);
-                        self.set_vx(&op_code, vx >> 1);
-                        self.set_carry(vx & 0b00000001u8 != 0);
+                        // The original interpreter shifts vy, modern ones shift vx in place
+                        let value = if self.quirks.shift_uses_vy { vy } else { vx };
+                        debug!("8XY6: V{}({}) = {} >> 1", op_code.x, vx, value);
+                        self.set_vx(&op_code, value >> 1);
+                        self.set_carry(value & 0b00000001u8 != 0);
                     },
                     0xEu8 => { // Shift left
-                        // debug!("8XYE: V{}({}) = V{}({} << 1)", op_code.x, vx, op_code.y, vy);
-                        // self.set_vx(&op_code, vy << 1);
-                        // self.set_carry(vy & 0b10000000u8 != 0);
-
-                        // We use the "modern" implementation of these instructions
-                        debug!("8XYE: V{}({}) <<= 1", op_code.x, vx);
-                        self.set_vx(&op_code, vx << 1);
-                        self.set_carry(vx & 0b10000000u8 != 0);
+                        let value = if self.quirks.shift_uses_vy { vy } else { vx };
+                        debug!("8XYE: V{}({}) = {} << 1", op_code.x, vx, value);
+                        self.set_vx(&op_code, value << 1);
+                        self.set_carry(value & 0b10000000u8 != 0);
                     },
-                    _ => panic!("Unimplemented op {:?}", op_code),
+                    _ => return Err(ExecutionError::UnknownOpCode(op_code.word())),
                 }
             },
             0xAu8 => { // Set index
@@ -233,8 +348,10 @@ impl State {
                 self.i = op_code.nnn;
             },
             0xBu8 => { // Jump with offset
-                debug!("BNNN: PC = NNN({}) + V0({})", op_code.nnn, self.v[0]);
-                self.pc = op_code.nnn + u16::from(self.v[0]);
+                // SCHIP reads the offset from VX instead, where X is the top nibble of NNN
+                let offset_register = if self.quirks.jump_uses_vx { usize::from(op_code.x) } else { 0 };
+                debug!("BNNN: PC = NNN({}) + V{}({})", op_code.nnn, offset_register, self.v[offset_register]);
+                self.pc = op_code.nnn + u16::from(self.v[offset_register]);
             },
             0xCu8 => { // Random
                 debug!("CXNN: Random & NN({})", op_code.nn);
@@ -254,14 +371,13 @@ impl State {
                 let mut did_collide = false;
 
                 // Draw a sprite n pixels high
-                let mut i = 0;
                 for row in 0..usize::from(op_code.n) {
                     let y = start_y + row;
                     if y >= 32 {
                         break;
                     }
 
-                    let sprite = self.ram[usize::from(self.i + i)];
+                    let sprite = self.read_ram(usize::from(self.i) + row, coverage::SPRITE)?;
                     for col in 0..8 {
                         let x = start_x + col;
                         if x >= 64 {
@@ -273,9 +389,6 @@ impl State {
                         self.display[x][y] = old_pixel ^ new_pixel;
                         did_collide = did_collide || (old_pixel && new_pixel);
                     }
-
-                    // Move onto the next sprite
-                    i += 1;
                 }
 
                 self.set_carry(did_collide);
@@ -294,7 +407,7 @@ impl State {
                             self.pc += 2;
                         }
                     },
-                    _ => panic!("Unimplemented op {:?}", op_code),
+                    _ => return Err(ExecutionError::UnknownOpCode(op_code.word())),
                 }
             },
             0xFu8 => {
@@ -346,32 +459,36 @@ impl State {
                         let digit2 = (vx % 100) / 10;
                         let digit1 = vx / 100;
 
-                        self.ram[usize::from(self.i)] = digit1;
-                        self.ram[usize::from(self.i + 1)] = digit2;
-                        self.ram[usize::from(self.i + 2)] = digit3;
+                        self.write_ram(usize::from(self.i), digit1)?;
+                        self.write_ram(usize::from(self.i) + 1, digit2)?;
+                        self.write_ram(usize::from(self.i) + 2, digit3)?;
                     },
                     0x55u8 => {
                         debug!("FX55: Store V0..V{} to I", op_code.x);
                         for i in 0..usize::from(op_code.x + 1) {
-                            self.ram[usize::from(self.i) + i] = self.v[i];
+                            self.write_ram(usize::from(self.i) + i, self.v[i])?;
                         }
                         for _i in 0..usize::from(op_code.x + 1) {
                             debug!("    Ram at {} is now {}", usize::from(self.i) + _i, self.ram[usize::from(self.i) + _i]);
                         }
+                        self.increment_i_after_load_store(&op_code);
                     },
                     0x65u8 => {
                         debug!("FX65: Load V0..V{} from I", op_code.x);
                         for i in 0..usize::from(op_code.x + 1) {
-                            self.v[i] = self.ram[usize::from(self.i) + i];
+                            self.v[i] = self.read_ram(usize::from(self.i) + i, coverage::DATA)?;
                         }
                         for _i in 0..usize::from(op_code.x + 1) {
                             debug!("    Loaded {} into V{}", self.v[_i], _i);
                         }
+                        self.increment_i_after_load_store(&op_code);
                     },
-                    _ => panic!("Unimplemented op {:?}", op_code),
+                    _ => return Err(ExecutionError::UnknownOpCode(op_code.word())),
                 }
             },
-            _ => panic!("Unimplemented op {:?}", op_code),
+            _ => return Err(ExecutionError::UnknownOpCode(op_code.word())),
         }
+
+        Ok(())
     }
 }
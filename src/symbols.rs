@@ -0,0 +1,111 @@
+use std::fs;
+
+use crate::loader;
+
+// Labels exported by an assembler, used to show addresses as main_loop+0x4
+// rather than 0x2A4.
+//
+// Symbol files are either lines of a label and an address in either order,
+// such as "main_loop 0x2A0", "0x2A0 main_loop" or "main_loop = 0x2A0", or a
+// JSON object of labels to addresses, such as {"main_loop": 672}.
+pub struct Symbols {
+    // Sorted by address
+    labels: Vec<(u16, String)>,
+}
+
+impl Symbols {
+    pub fn from_labels(mut labels: Vec<(u16, String)>) -> Self {
+        labels.sort();
+        Self {
+            labels,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read symbol file {}: {}", path, e))?;
+        Symbols::parse(&text)
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // The symbol file that sits alongside a rom, e.g. pong.sym for pong.ch8
+    pub fn default_path(rom_file: &str) -> Option<String> {
+        let path = loader::companion_path(rom_file, "sym");
+        if path.is_file() {
+            path.to_str().map(String::from)
+        } else {
+            None
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut labels = Vec::new();
+
+        if text.trim_start().starts_with('{') {
+            let pairs = text.trim().trim_start_matches('{').trim_end_matches('}');
+            for pair in pairs.split(',').filter(|pair| !pair.trim().is_empty()) {
+                let mut parts = pair.splitn(2, ':');
+                let name = parts.next().unwrap().trim().trim_matches('"');
+                let address = parts.next()
+                    .and_then(|address| parse_address(address.trim()))
+                    .ok_or(format!("Invalid symbol {}", pair.trim()))?;
+                labels.push((address, name.to_string()));
+            }
+        } else {
+            for (line_number, line) in text.lines().enumerate() {
+                let line = line.split(['#', ';']).next().unwrap();
+                let tokens: Vec<&str> = line
+                    .split(|c: char| c.is_whitespace() || c == '=' || c == ',')
+                    .map(|token| token.trim_start_matches(':'))
+                    .filter(|token| !token.is_empty())
+                    .collect();
+
+                match tokens.as_slice() {
+                    [] => continue,
+                    [first, second] => {
+                        let symbol = match (parse_address(first), parse_address(second)) {
+                            (_, Some(address)) => (address, first.to_string()),
+                            (Some(address), None) => (address, second.to_string()),
+                            (None, None) => return Err(format!("line {}: No address in {}", line_number + 1, line.trim())),
+                        };
+                        labels.push(symbol);
+                    },
+                    _ => return Err(format!("line {}: Expected a label and an address", line_number + 1)),
+                }
+            }
+        }
+
+        labels.sort();
+        Ok(Self {
+            labels,
+        })
+    }
+
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.labels.iter()
+            .find(|(label_address, _)| *label_address == address)
+            .map(|(_, name)| name.as_str())
+    }
+
+    // The nearest label at or before the address, with an offset, or just the
+    // address if there is no such label
+    pub fn resolve(&self, address: u16) -> String {
+        let label = self.labels.iter()
+            .rev()
+            .find(|(label_address, _)| *label_address <= address);
+
+        match label {
+            Some((label_address, name)) if *label_address == address => name.clone(),
+            Some((label_address, name)) => format!("{}+{:#X}", name, address - label_address),
+            None => format!("{:#05X}", address),
+        }
+    }
+}
+
+pub fn parse_address(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).or_else(|| text.strip_prefix('$')) {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::palette::Palette;
+use crate::renderer::Renderer;
+
+// Draws the screen with text, for playing over SSH
+pub struct Terminal {
+    style: Style,
+    // The characters currently on screen, row by row, or None when everything
+    // needs drawing
+    cells: Option<Vec<char>>,
+    palette: Palette,
+    // The settings to put back when done, from stty -g
+    saved_mode: String,
+    input: Receiver<Vec<u8>>,
+}
+
+#[derive(Clone, Copy)]
+pub enum Style {
+    // Two pixels per character, one above the other
+    HalfBlocks,
+    // Eight pixels per character, two across and four down
+    Braille,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Escape,
+}
+
+impl Style {
+    fn cell_size(self) -> (usize, usize) {
+        match self {
+            Style::HalfBlocks => (1, 2),
+            Style::Braille => (2, 4),
+        }
+    }
+
+    fn cell(self, frame_buffer: &[[bool; 32]; 64], column: usize, row: usize) -> char {
+        let (width, height) = self.cell_size();
+        let pixel = |x: usize, y: usize| frame_buffer[column * width + x][row * height + y];
+        match self {
+            Style::HalfBlocks => match (pixel(0, 0), pixel(0, 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            },
+            Style::Braille => {
+                // Dots 1-3 are down the left and 4-6 down the right, with 7
+                // and 8 below them
+                const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+                let mut bits = 0;
+                for (x, column_dots) in DOTS.iter().enumerate() {
+                    for (y, dot) in column_dots.iter().enumerate() {
+                        if pixel(x, y) {
+                            bits |= dot;
+                        }
+                    }
+                }
+                std::char::from_u32(0x2800 + bits).unwrap_or(' ')
+            },
+        }
+    }
+}
+
+impl Terminal {
+    // Switch the terminal to a blank screen which reads keys as they are
+    // pressed, until this is dropped
+    pub fn new(style: Style) -> Result<Self, String> {
+        let saved_mode = stty(&["-g"])?;
+        // Ctrl-C is read as a key, so that the terminal is always put back
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 64];
+            while let Ok(length) = io::stdin().lock().read(&mut buffer) {
+                if length == 0 || sender.send(buffer[..length].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // The alternate screen, without a cursor
+        print!("\x1b[?1049h\x1b[?25l");
+
+        Ok(Self {
+            style,
+            cells: None,
+            palette: Palette::default(),
+            saved_mode: saved_mode.trim().to_string(),
+            input,
+        })
+    }
+
+    // The keys pressed since last time. Terminals don't say when keys are
+    // released.
+    pub fn keys(&mut self) -> Vec<Key> {
+        let mut keys = Vec::new();
+        while let Ok(bytes) = self.input.try_recv() {
+            let mut bytes = bytes.iter().peekable();
+            while let Some(byte) = bytes.next() {
+                let key = match byte {
+                    // Escape starts the sequences sent by the arrow keys, and
+                    // is a key on its own
+                    0x1B => match bytes.peek() {
+                        Some(b'[') | Some(b'O') => {
+                            bytes.next();
+                            match bytes.next() {
+                                Some(b'A') => Key::Up,
+                                Some(b'B') => Key::Down,
+                                Some(b'C') => Key::Right,
+                                Some(b'D') => Key::Left,
+                                _ => continue,
+                            }
+                        },
+                        _ => Key::Escape,
+                    },
+                    // Ctrl-C
+                    0x03 => Key::Escape,
+                    b'\r' | b'\n' => Key::Char('\r'),
+                    byte => Key::Char((*byte as char).to_ascii_lowercase()),
+                };
+                keys.push(key);
+            }
+        }
+        keys
+    }
+}
+
+impl Renderer for Terminal {
+    // Only the characters which changed are written
+    fn draw_frame(&mut self, frame_buffer: &[[bool; 32]; 64]) {
+        let (width, height) = self.style.cell_size();
+        let (columns, rows) = (64 / width, 32 / height);
+
+        let mut output = String::new();
+        if self.cells.is_none() {
+            let (background, fill) = (self.palette.background, self.palette.fill);
+            output += &format!(
+                "\x1b[48;2;{};{};{}m\x1b[38;2;{};{};{}m\x1b[2J",
+                background.0, background.1, background.2, fill.0, fill.1, fill.2,
+            );
+        }
+        let cells = self.cells.get_or_insert_with(|| vec!['\0'; columns * rows]);
+
+        for row in 0..rows {
+            // Where the cursor is, to avoid moving it to the next character
+            let mut cursor = None;
+            for column in 0..columns {
+                let cell = self.style.cell(frame_buffer, column, row);
+                if cells[row * columns + column] == cell {
+                    continue;
+                }
+                cells[row * columns + column] = cell;
+                if cursor != Some(column) {
+                    output += &format!("\x1b[{};{}H", row + 1, column + 1);
+                }
+                output.push(cell);
+                cursor = Some(column + 1);
+            }
+        }
+
+        if !output.is_empty() {
+            let mut stdout = io::stdout();
+            stdout.write_all(output.as_bytes()).and_then(|_| stdout.flush()).expect("Failed to draw to the terminal");
+        }
+    }
+
+    fn set_title(&mut self, title: &str) {
+        print!("\x1b]0;{}\x07", title);
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = *palette;
+        self.cells = None;
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        io::stdout().flush().ok();
+        stty(&[self.saved_mode.as_str()]).ok();
+    }
+}
+
+// Change the settings of the terminal
+fn stty(args: &[&str]) -> Result<String, String> {
+    let tty = File::open("/dev/tty").map_err(|e| format!("Failed to open the terminal: {}", e))?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(tty)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to run stty: {}", e))?;
+    if !output.status.success() {
+        return Err("Failed to set up the terminal".to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::time::Instant;
 use std::vec::Vec;
 
@@ -39,6 +40,14 @@ impl TimedSystem {
         );
     }
 
+    // Number of cycles that can be executed while still being < target_nanos
+    fn num_cycles_before(&self, target_nanos: u128) -> u64 {
+        let next_nanos = u128::from(self.next_cycle_nanos());
+        let cycle_duration_nanos = u128::from(self.cycle_duration_nanos);
+        let num_cycles = (target_nanos - next_nanos).div_ceil(cycle_duration_nanos);
+        u64::try_from(num_cycles).unwrap_or(u64::MAX)
+    }
+
     fn divide_round_up(a: u64, b: u64) -> u64 {
         return (a + b - 1) / b;
     }
@@ -50,7 +59,12 @@ pub struct Instruction {
 }
 
 pub struct Timing {
-    start_time: Instant,
+    // Emulated time runs relative to the last time it was paused or resumed,
+    // or had its speed changed
+    anchor_time: Instant,
+    anchor_nanos: u128,
+    speed: f64,
+    paused: bool,
     systems: Vec<TimedSystem>,
 }
 
@@ -61,14 +75,73 @@ impl Timing {
         systems: Vec<TimedSystem>,
     ) -> Self {
         Self {
-            start_time: current_time,
+            anchor_time: current_time,
+            anchor_nanos: 0,
+            speed: 1.0,
+            paused: false,
             systems,
         }
     }
 
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    // How much emulated time has passed, taking into account pauses and speed
+    fn emulated_nanos(&self, current_time: Instant) -> u128 {
+        if self.paused {
+            return self.anchor_nanos;
+        }
+
+        let real_nanos = (current_time - self.anchor_time).as_nanos();
+        self.anchor_nanos + (real_nanos as f64 * self.speed) as u128
+    }
+
+    fn rebase(&mut self, current_time: Instant) {
+        self.anchor_nanos = self.emulated_nanos(current_time);
+        self.anchor_time = current_time;
+    }
+
+    pub fn pause(&mut self, current_time: Instant) {
+        self.rebase(current_time);
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self, current_time: Instant) {
+        self.rebase(current_time);
+        self.paused = false;
+    }
+
+    pub fn set_speed(&mut self, current_time: Instant, speed: f64) {
+        self.rebase(current_time);
+        self.speed = speed;
+    }
+
     pub fn get_instructions(&mut self, current_time: Instant) -> Vec<Instruction> {
-        let required_nanos = (current_time - self.start_time).as_nanos();
+        let required_nanos = self.emulated_nanos(current_time);
+        self.instructions_until(required_nanos)
+    }
+
+    // Pause, then advance emulated time up to and including the next cycle of
+    // the named system. Any cycles of other systems which are due before then
+    // are included, so that the systems stay in step with each other.
+    pub fn step(&mut self, current_time: Instant, name: &str) -> Vec<Instruction> {
+        self.pause(current_time);
+
+        let target_nanos = match self.systems.iter().find(|system| system.name == name) {
+            Some(system) => u128::from(system.next_cycle_nanos()) + 1,
+            None => return Vec::new(),
+        };
+        self.anchor_nanos = self.anchor_nanos.max(target_nanos);
+
+        self.instructions_until(target_nanos)
+    }
 
+    fn instructions_until(&mut self, required_nanos: u128) -> Vec<Instruction> {
         let mut results: Vec<Instruction> = Vec::new();
         let mut watchdog = 0;
         loop {
@@ -95,8 +168,10 @@ impl Timing {
             // TODO currently assume there is more than one system
 
             // We put as many cycles as we can from the soonest system, until
-            // it is no longer the soonest system
-            let num_cycles = self.systems[0].num_cycles_until(next_soonest_nanos);
+            // it is no longer the soonest system, without going past the
+            // required time
+            let num_cycles = self.systems[0].num_cycles_until(next_soonest_nanos)
+                .min(self.systems[0].num_cycles_before(required_nanos));
 
             // Add it to our results
             debug!("Adding instruction {} for {} cycles", self.systems[0].name, num_cycles);
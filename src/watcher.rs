@@ -0,0 +1,54 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::loader;
+
+// How often to look at the file
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Notices when a rom's file changes on disk, by polling its modification time
+// and size
+pub struct Watcher {
+    path: String,
+    loaded: Option<(SystemTime, u64)>,
+    // What was seen at the last poll, so that a file is only reloaded once
+    // it has stopped changing, rather than halfway through being written
+    seen: Option<(SystemTime, u64)>,
+    last_poll: Instant,
+}
+
+impl Watcher {
+    pub fn new(rom_file: &str, now: Instant) -> Self {
+        let path = loader::file_path(rom_file).to_string();
+        let stamp = stamp(&path);
+        Self {
+            path,
+            loaded: stamp,
+            seen: stamp,
+            last_poll: now,
+        }
+    }
+
+    // Whether the file has changed since it was loaded, and has settled
+    pub fn changed(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.last_poll) < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = now;
+
+        let current = stamp(&self.path);
+        let settled = current == self.seen;
+        self.seen = current;
+        if settled && current.is_some() && current != self.loaded {
+            self.loaded = current;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn stamp(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
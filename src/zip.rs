@@ -0,0 +1,119 @@
+use crate::crc32::crc32;
+use crate::inflate;
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054B50;
+const CENTRAL_DIRECTORY_ENTRY: u32 = 0x02014B50;
+const LOCAL_HEADER: u32 = 0x04034B50;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+// A zip archive read from memory, without unpacking it to disk
+pub struct Archive<'a> {
+    data: &'a [u8],
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    name: String,
+    method: u16,
+    encrypted: bool,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    local_header: usize,
+}
+
+impl<'a> Archive<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, String> {
+        // The end of central directory record is at the end, before a comment
+        // of up to 64k
+        let end = (0..=data.len().saturating_sub(22))
+            .rev()
+            .take(22 + 0xFFFF)
+            .find(|position| read_u32(data, *position) == Some(END_OF_CENTRAL_DIRECTORY))
+            .ok_or("Not a zip archive")?;
+        let entry_count = read_u16(data, end + 10).ok_or("Zip archive is truncated")?;
+        let mut position = read_u32(data, end + 16).ok_or("Zip archive is truncated")? as usize;
+
+        let mut entries = Vec::new();
+        for _ in 0..entry_count {
+            if read_u32(data, position) != Some(CENTRAL_DIRECTORY_ENTRY) {
+                return Err("Zip archive's directory is corrupt".to_string());
+            }
+            let field = |offset: usize| read_u16(data, position + offset).ok_or("Zip archive is truncated");
+            let long_field = |offset: usize| read_u32(data, position + offset).ok_or("Zip archive is truncated");
+
+            let name_length = field(28)? as usize;
+            let extra_length = field(30)? as usize;
+            let comment_length = field(32)? as usize;
+            let name = data.get(position + 46..position + 46 + name_length).ok_or("Zip archive is truncated")?;
+
+            entries.push(Entry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: field(10)?,
+                encrypted: field(8)? & 1 != 0,
+                crc: long_field(16)?,
+                compressed_size: long_field(20)? as usize,
+                size: long_field(24)? as usize,
+                local_header: long_field(42)? as usize,
+            });
+            position += 46 + name_length + extra_length + comment_length;
+        }
+
+        Ok(Self {
+            data,
+            entries,
+        })
+    }
+
+    // The names of every file in the archive, in the order they are stored
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.iter()
+            .filter(|entry| !entry.name.ends_with('/'))
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+
+    // A file, which is refused if it is bigger than max_size
+    pub fn read(&self, name: &str, max_size: usize) -> Result<Vec<u8>, String> {
+        let entry = self.entries.iter()
+            .find(|entry| entry.name == name)
+            .ok_or(format!("{} isn't in the archive", name))?;
+        if entry.encrypted {
+            return Err(format!("{} is encrypted", name));
+        }
+        if entry.size > max_size {
+            return Err(format!("{} is {} bytes, more than the {} it can be", name, entry.size, max_size));
+        }
+
+        let header = entry.local_header;
+        if read_u32(self.data, header) != Some(LOCAL_HEADER) {
+            return Err(format!("{} is corrupt", name));
+        }
+        let name_length = read_u16(self.data, header + 26).ok_or("Zip archive is truncated")? as usize;
+        let extra_length = read_u16(self.data, header + 28).ok_or("Zip archive is truncated")? as usize;
+        let start = header + 30 + name_length + extra_length;
+        let compressed = self.data.get(start..start + entry.compressed_size).ok_or("Zip archive is truncated")?;
+
+        let data = match entry.method {
+            STORED => compressed.to_vec(),
+            DEFLATED => inflate::inflate(compressed, entry.size).map_err(|e| format!("{} is corrupt: {}", name, e))?,
+            method => return Err(format!("{} uses unsupported compression method {}", name, method)),
+        };
+        if data.len() != entry.size || crc32(&data) != entry.crc {
+            return Err(format!("{} is corrupt (checksum mismatch)", name));
+        }
+        Ok(data)
+    }
+}
+
+fn read_u16(data: &[u8], position: usize) -> Option<u16> {
+    let bytes = data.get(position..position + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], position: usize) -> Option<u32> {
+    let bytes = data.get(position..position + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}